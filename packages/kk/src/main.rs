@@ -6,7 +6,10 @@ use fltk::{
     prelude::{GroupExt, WidgetBase, WidgetExt, WindowExt},
     window::{GlWindow, Window},
 };
-use kr::db::SimpleJsonDatabase;
+use kr::{
+    db::{BackgroundScan, Database, IndexedMovieData, MovieId, ScanProgress, ScanResult},
+    watch::LibraryWatcher,
+};
 use libmpv2::Mpv;
 use serde_json::json;
 use std::{
@@ -14,46 +17,79 @@ use std::{
     env,
     path::PathBuf,
     rc::Rc,
+    sync::Mutex,
+    time::{Duration, Instant},
 };
 
-use crate::ui::browse::{BrowseMenu, MenuMode};
+use crate::ui::browse::{BrowseMenu, ItemKind, MenuMode, RenderItem, ShowLocation, season_title};
 
 mod ui;
 
 const INIT_WIN_WIDTH: i32 = 1280;
 const INIT_WIN_HEIGHT: i32 = 720;
+// one progress event per parsed nfo would flood the ui
+const SCAN_PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
 
 #[derive(Clone, Debug)]
 enum AppHandleEvent {
-    TimePosUpdated(f64),
-    GoToVideo(String, Option<Vec<f64>>),
+    TimePosUpdated(f64, f64),
+    GoToVideo(MovieId, String, Vec<f64>, Option<f64>),
+    AddMarker(f64),
+    ToggleFav,
+    EndOfFile,
     GoToMenu,
     FullScreen(Option<bool>),
     SetCusor(Cursor),
+    LibraryChanged(Vec<PathBuf>),
+    Rescan(bool),
+    ScanProgressed(ScanProgress),
+    ScanFinished(ScanResult),
     End,
 }
 
 #[derive(Clone, Debug)]
 enum MpvEvent {
-    LoadFile(String),
+    LoadFile(String, Option<f64>),
     SetMarker(Vec<f64>),
     Stop,
     JumpNextMarker,
     TogglePause,
     TriggerMarkerSend,
+    ShowText(String),
     MouseMove(i32, i32),
     MouseClick(i32, i32),
 }
 
 fn main() {
-    let search_path = env::var("KK_SEARCH_PATH").expect("KK_SEARCH_PATH env variable is required");
-    let search_path = PathBuf::from(search_path);
+    let search_path = env::var_os("KK_SEARCH_PATH").expect("KK_SEARCH_PATH env variable is required");
+    let search_paths: Vec<PathBuf> = env::split_paths(&search_path).collect();
 
+    #[cfg(not(feature = "sqlite"))]
     let mut db = kr::init();
-    db.load_config(&search_path).ok();
-    let db = Rc::new(RefCell::new(db));
+    #[cfg(feature = "sqlite")]
+    let mut db = kr::init_sqlite().expect("open sqlite database failed");
+    // the cached library is shown right away, the scan runs in the background
+    if let Err(e) = db.load_cached(&search_paths) {
+        // scanning would replace the stored library with an empty one
+        let message = format!("The library could not be loaded and was left untouched:\n{e:#}");
+        eprintln!("{message}");
+        fltk::dialog::alert_default(&message);
+        std::process::exit(1);
+    }
+    // roots dropped from KK_SEARCH_PATH are disabled, their entries are kept
+    report(db.sync_roots(&search_paths));
 
     let (app_tx, app_rx) = app::channel::<AppHandleEvent>();
+
+    let mut watcher = report(LibraryWatcher::new(db.video_extensions(), enclose!((app_tx) move |paths| {
+        app_tx.send(AppHandleEvent::LibraryChanged(paths));
+    })))
+    .map(|mut watcher| {
+        watcher.sync(db.roots());
+        watcher
+    });
+
+    let db = Rc::new(RefCell::new(db));
     let (mpv_tx, mpv_rx) = std::sync::mpsc::channel::<MpvEvent>();
 
     let app = app::App::default();
@@ -67,8 +103,9 @@ fn main() {
         .with_size(INIT_WIN_WIDTH, INIT_WIN_HEIGHT)
         .center_of_parent();
 
-    let menu = BrowseMenu::new(INIT_WIN_WIDTH, INIT_WIN_HEIGHT);
-    draw_menu_with_mode(menu.clone(), db.clone(), MenuMode::AddedTime);
+    let mut menu = BrowseMenu::new(INIT_WIN_WIDTH, INIT_WIN_HEIGHT);
+    menu.set_scan_progress(Some(ScanProgress::default()));
+    draw_menu_with_mode(menu.clone(), db.clone(), MenuMode::All);
 
     let video_group = Group::default()
         .with_size(INIT_WIN_WIDTH, INIT_WIN_HEIGHT)
@@ -82,6 +119,9 @@ fn main() {
     win.end();
     win.show();
 
+    // started once the window is up so the cached library shows without delay
+    spawn_scan(db.borrow().background_scan(), app_tx);
+
     let wid = video_layer.raw_handle() as i64;
 
     let mut mpv = Mpv::new().expect("Main MPV init failed");
@@ -119,7 +159,9 @@ fn main() {
                                 app_tx.send(AppHandleEvent::SetCusor(cursor));
                             }
                             "rust_add_marker" => {
-                                // todo
+                                if let Some(Ok(time)) = args.get(1).map(|t| t.parse::<f64>()) {
+                                    app_tx.send(AppHandleEvent::AddMarker(time));
+                                }
                             }
                             _ => {}
                         }
@@ -133,7 +175,7 @@ fn main() {
                         match event_name {
                             "time-pos" => {
                                 if total_dur > 0. {
-                                    app_tx.send(AppHandleEvent::TimePosUpdated(val / total_dur));
+                                    app_tx.send(AppHandleEvent::TimePosUpdated(val, total_dur));
                                 }
                             }
                             "duration" => {
@@ -142,6 +184,9 @@ fn main() {
                             _ => ()
                         }
                     }
+                    EndFile(reason) if reason == libmpv2::mpv_end_file_reason::Eof => {
+                        app_tx.send(AppHandleEvent::EndOfFile);
+                    }
                     _ => ()
                 }
             }
@@ -149,7 +194,10 @@ fn main() {
             if let Ok(evt) = mpv_rx.try_recv() {
                 use MpvEvent::*;
                 match evt {
-                    LoadFile(path) => {
+                    LoadFile(path, start) => {
+                        // `start` is a global option, reset it so other files play from the beginning
+                        let start = start.map(|s| s.to_string()).unwrap_or_else(|| "none".to_string());
+                        mpv.set_property("start", start.as_str()).ok();
                         mpv.command("loadfile", &[&path]).ok();
                     }
                     Stop => {
@@ -168,6 +216,9 @@ fn main() {
                     TriggerMarkerSend => {
                         mpv.command("script-message", &["trigger_marker_send"]).ok();
                     }
+                    ShowText(text) => {
+                        mpv.command("show-text", &[&text]).ok();
+                    }
                     #[allow(unused)]
                     MouseMove(x, y) => {
                         #[cfg(target_os = "windows")]
@@ -184,6 +235,12 @@ fn main() {
     }));
 
     let in_video = Rc::new(Cell::new(false));
+    let playing: RefCell<Option<MovieId>> = RefCell::new(None);
+    let progress: Cell<Option<(f64, f64)>> = Cell::new(None);
+    // applied once back in the menu, the grid is not rebuilt behind the video
+    let mut library_changes: Vec<PathBuf> = vec![];
+    let mut scan_result: Option<ScanResult> = None;
+    let mut scanning = true;
     let mut mouse_event_throttle = 0u8;
     win.handle(enclose!((app_tx, mpv_tx, in_video, mut menu, db) move |win, ev| {
         match ev {
@@ -213,25 +270,76 @@ fn main() {
             }
             Event::KeyDown|Event::Shortcut => {
                 let key = app::event_key();
+                // while searching every printable key goes to the query
+                if !in_video.get() && menu.is_searching() && key != Key::Enter {
+                    match key {
+                        Key::Escape => {
+                            menu.end_search();
+                            draw_menu_with_mode(menu.clone(), db.clone(), menu.mode());
+                        }
+                        Key::BackSpace => {
+                            menu.pop_search_char();
+                            draw_menu_with_search(menu.clone(), db.clone());
+                        }
+                        Key::Down => {
+                            menu.select_next();
+                            menu.draw();
+                        }
+                        Key::Up => {
+                            menu.select_prev();
+                            menu.draw();
+                        }
+                        Key::Tab => {
+                            if app::is_event_shift() {
+                                menu.select_prev();
+                            } else {
+                                menu.select_next();
+                            }
+                            menu.draw();
+                        }
+                        _ => {
+                            let text = app::event_text();
+                            if !text.is_empty() && !text.chars().any(char::is_control) {
+                                menu.push_search_text(&text);
+                                draw_menu_with_search(menu.clone(), db.clone());
+                            }
+                        }
+                    }
+                    return true;
+                }
+
                 return match key {
                     Key::Enter => {
-                        if let Some(i) = menu.page_first_item_path() && let Some(data) = db.borrow().get_movie(i as usize) {
-                            let parent = data.path.parent().unwrap();
-                            let filename = data.path.file_prefix().unwrap().to_str().unwrap();
-
-                            for ext in ["mp4", "mkv", "avi", "rmvb"] {
-                                let p = parent.join(format!("{filename}.{ext}"));
-                                if p.exists() {
-                                    println!("playing {:?}", &p);
-                                    app_tx.send(AppHandleEvent::GoToVideo(p.to_string_lossy().to_string(), None));
-                                    return true;
-                                }
+                        match menu.page_first_item() {
+                            Some(ItemKind::Movie(id)) => {
+                                // shift+enter plays from the beginning
+                                play(&db.borrow(), &id, !app::is_event_shift(), &app_tx)
+                            }
+                            Some(ItemKind::Show(show)) => {
+                                let Some(title) = db.borrow().get_show(&show).map(|s| s.show.title.clone()) else {
+                                    return false;
+                                };
+                                menu.open_show(show, title);
+                                refresh_menu(menu.clone(), db.clone());
+                                true
                             }
+                            Some(ItemKind::Season(show, season)) => {
+                                let Some(title) = db.borrow().get_show(&show).map(|s| season_title(s, season)) else {
+                                    return false;
+                                };
+                                menu.open_season(season, title);
+                                refresh_menu(menu.clone(), db.clone());
+                                true
+                            }
+                            None => false,
                         }
-                        false
                     }
                     Key::Escape => {
-                        app_tx.send(AppHandleEvent::End);
+                        if !in_video.get() && menu.close_show_level() {
+                            refresh_menu(menu.clone(), db.clone());
+                        } else {
+                            app_tx.send(AppHandleEvent::End);
+                        }
                         true
                     }
                     Key::BackSpace => {
@@ -309,10 +417,41 @@ fn main() {
                         mpv_tx.send(MpvEvent::TogglePause).ok();
                         true
                     }
+                    k if k == Key::from_char('v') => {
+                        if in_video.get() {
+                            app_tx.send(AppHandleEvent::ToggleFav);
+                        } else if let Some(ItemKind::Movie(id)) = menu.page_first_item() {
+                            report(db.borrow_mut().toggle_fav(&id));
+                            refresh_menu(menu.clone(), db.clone());
+                        }
+                        true
+                    }
                     k if k == Key::from_char('b') && !in_video.get() => {
                         draw_menu_with_mode(menu.clone(), db.clone(), menu.prev_mode());
                         true
                     }
+                    k if k == Key::from_char('s') && !in_video.get() => {
+                        menu.next_sort();
+                        menu.set_page(1);
+                        draw_menu_with_mode(menu.clone(), db.clone(), menu.mode());
+                        true
+                    }
+                    k if k == Key::from_char('r') && !in_video.get() => {
+                        menu.reverse_sort();
+                        menu.set_page(1);
+                        draw_menu_with_mode(menu.clone(), db.clone(), menu.mode());
+                        true
+                    }
+                    // shift+f5 re-reads everything, not only what changed
+                    Key::F5 if !in_video.get() => {
+                        app_tx.send(AppHandleEvent::Rescan(app::is_event_shift()));
+                        true
+                    }
+                    k if k == Key::from_char('/') && !in_video.get() => {
+                        menu.start_search();
+                        draw_menu_with_search(menu.clone(), db.clone());
+                        true
+                    }
                     _ => false
                 };
             }
@@ -327,16 +466,84 @@ fn main() {
 
         use AppHandleEvent::*;
         match ev {
-            TimePosUpdated(_new_time) => {}
-            GoToVideo(p, m) => {
+            TimePosUpdated(pos, duration) => {
+                if playing.borrow().is_some() {
+                    progress.set(Some((pos, duration)));
+                }
+            }
+            GoToVideo(id, p, m, start) => {
                 in_video.set(true);
+                progress.set(None);
+                report(db.borrow_mut().record_play(&id));
+                playing.replace(Some(id));
                 wizard.set_current_widget(&video_group);
-                mpv_tx.send(MpvEvent::LoadFile(p)).ok();
-                if let Some(m) = m {
-                    mpv_tx.send(MpvEvent::SetMarker(m)).ok();
+                mpv_tx.send(MpvEvent::LoadFile(p, start)).ok();
+                // always resend so markers of the previous movie are cleared
+                mpv_tx.send(MpvEvent::SetMarker(m)).ok();
+            }
+            AddMarker(time) => {
+                let Some(id) = playing.borrow().clone() else {
+                    continue;
+                };
+
+                match db.borrow_mut().add_marker(&id, time) {
+                    Ok(Some(m)) => {
+                        mpv_tx.send(MpvEvent::SetMarker(m.to_vec())).ok();
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        eprintln!("{e:?}");
+                        mpv_tx.send(MpvEvent::ShowText("Saving marker failed".to_string())).ok();
+                    }
+                }
+            }
+            ToggleFav => {
+                let Some(id) = playing.borrow().clone() else {
+                    continue;
+                };
+
+                let text = match db.borrow_mut().toggle_fav(&id) {
+                    Ok(Some(true)) => "Added to favorites",
+                    Ok(Some(false)) => "Removed from favorites",
+                    Ok(None) => continue,
+                    Err(e) => {
+                        eprintln!("{e:?}");
+                        "Saving favorite failed"
+                    }
+                };
+                mpv_tx.send(MpvEvent::ShowText(text.to_string())).ok();
+            }
+            EndOfFile => {
+                progress.set(None);
+                let Some(id) = playing.borrow().clone() else {
+                    continue;
+                };
+
+                let mut db = db.borrow_mut();
+                report(db.set_resume_pos(&id, None));
+                report(db.set_watched(&id, true));
+
+                if let Some(next) = db.next_episode(&id) {
+                    play(&db, &next, true, &app_tx);
                 }
             }
             GoToMenu => {
+                if let Some(id) = playing.take() && let Some((pos, duration)) = progress.take() {
+                    report(db.borrow_mut().save_progress(&id, pos, duration));
+                }
+
+                if let Some(result) = scan_result.take() {
+                    finish_scan(&mut db.borrow_mut(), watcher.as_mut(), result);
+                    menu.set_scan_progress(None);
+                    scanning = false;
+                }
+
+                if !scanning {
+                    scanning = scan_library_changes(&db.borrow(), &mut library_changes, app_tx);
+                }
+
+                // history may have changed while playing
+                refresh_menu(menu.clone(), db.clone());
                 wizard.set_current_widget(&menu.g);
                 in_video.set(false);
                 mpv_tx.send(MpvEvent::Stop).ok();
@@ -356,7 +563,45 @@ fn main() {
                     win.set_cursor(cursor);
                 }
             }
-            End => break,
+            LibraryChanged(paths) => {
+                // kept until the running scan is done, it may have missed them
+                library_changes.extend(paths);
+                if !in_video.get() && !scanning {
+                    scanning = scan_library_changes(&db.borrow(), &mut library_changes, app_tx);
+                }
+            }
+            Rescan(full) => {
+                if scanning {
+                    continue;
+                }
+
+                let scan = db.borrow().background_scan();
+                spawn_scan(if full { scan.full() } else { scan }, app_tx);
+                scanning = true;
+                menu.set_scan_progress(Some(ScanProgress::default()));
+            }
+            ScanProgressed(progress) => {
+                if scanning {
+                    menu.set_scan_progress(Some(progress));
+                }
+            }
+            ScanFinished(result) => {
+                if in_video.get() {
+                    scan_result = Some(result);
+                    continue;
+                }
+
+                finish_scan(&mut db.borrow_mut(), watcher.as_mut(), result);
+                menu.set_scan_progress(None);
+                scanning = scan_library_changes(&db.borrow(), &mut library_changes, app_tx);
+                refresh_menu(menu.clone(), db.clone());
+            }
+            End => {
+                if let Some(id) = playing.take() && let Some((pos, duration)) = progress.take() {
+                    report(db.borrow_mut().save_progress(&id, pos, duration));
+                }
+                break;
+            }
         }
     }
 }
@@ -383,14 +628,167 @@ fn mpv_property(mpv: &Mpv) {
     mpv.observe_property("duration", Format::Double, 1).unwrap();
 }
 
-fn draw_menu_with_mode(mut menu: BrowseMenu, db: Rc<RefCell<SimpleJsonDatabase>>, mode: MenuMode) {
-    let mut db = db.borrow_mut();
-    let iter = match mode {
-        MenuMode::AddedTime => db.order_by_added_time(),
-        MenuMode::Random => db.order_by_random(),
-        MenuMode::Fav => db.filter_by_fav(),
+/// Prints library errors, the app keeps going with its in-memory state.
+fn report<T>(result: anyhow::Result<T>) -> Option<T> {
+    result.map_err(|e| eprintln!("{e:?}")).ok()
+}
+
+/// Prints the nfo files the last scan couldn't parse.
+fn report_scan(db: &Database) {
+    for failure in &db.scan_report().failures {
+        eprintln!("{:?} skipped: {}", failure.path, failure.error);
+    }
+}
+
+/// Scans the library on a worker thread, the result comes back as `ScanFinished`.
+fn spawn_scan(scan: BackgroundScan, app_tx: app::Sender<AppHandleEvent>) {
+    std::thread::spawn(move || {
+        let last_sent = Mutex::new(Instant::now());
+        let result = scan.run(|progress| {
+            if let Ok(mut last_sent) = last_sent.try_lock()
+                && last_sent.elapsed() >= SCAN_PROGRESS_INTERVAL
+            {
+                *last_sent = Instant::now();
+                app_tx.send(AppHandleEvent::ScanProgressed(progress));
+            }
+        });
+        app_tx.send(AppHandleEvent::ScanFinished(result));
+    });
+}
+
+/// Replaces the cached library with the result of the background scan and
+/// watches the roots it ended up with.
+fn finish_scan(db: &mut Database, watcher: Option<&mut LibraryWatcher>, result: ScanResult) {
+    report(db.finish_scan(result));
+    report_scan(db);
+
+    if let Some(watcher) = watcher {
+        watcher.sync(db.roots());
+    }
+}
+
+/// Scans the roots of the paths reported by the watcher in the background,
+/// returns whether a scan was started.
+fn scan_library_changes(
+    db: &Database,
+    paths: &mut Vec<PathBuf>,
+    app_tx: app::Sender<AppHandleEvent>,
+) -> bool {
+    if paths.is_empty() {
+        return false;
+    }
+
+    let scan = db.background_scan().changed(&std::mem::take(paths));
+    if scan.is_empty() {
+        return false;
+    }
+
+    spawn_scan(scan, app_tx);
+    true
+}
+
+/// Starts playing the videos of entry `id`.
+fn play(db: &Database, id: &MovieId, resume: bool, app_tx: &app::Sender<AppHandleEvent>) -> bool {
+    let Some(data) = db.get_movie(id) else {
+        return false;
+    };
+
+    if data.videos.is_empty() {
+        eprintln!("{:?} has no video", data.path);
+        return false;
+    }
+
+    println!("playing {:?}", data.videos);
+    let markers = db.markers(id).to_vec();
+    let start = if resume { db.resume_pos(id) } else { None };
+    app_tx.send(AppHandleEvent::GoToVideo(id.clone(), mpv_url(&data.videos), markers, start));
+    true
+}
+
+/// The parts of a multi-part release are joined into one mpv EDL timeline,
+/// so seeking, markers and the resume position span all of them.
+fn mpv_url(videos: &[PathBuf]) -> String {
+    if let [video] = videos {
+        return video.to_string_lossy().to_string();
+    }
+
+    // `%len%` prefixes let paths contain the `;` and `,` separators
+    let parts: Vec<String> = videos
+        .iter()
+        .map(|v| {
+            let path = v.to_string_lossy();
+            format!("%{}%{path}", path.len())
+        })
+        .collect();
+    format!("edl://{}", parts.join(";"))
+}
+
+fn render_items<'a>(iter: impl Iterator<Item = IndexedMovieData<'a>>) -> Vec<RenderItem> {
+    iter.flat_map(|item| item.try_into().ok()).collect()
+}
+
+fn show_items(db: &mut Database, location: Option<ShowLocation>) -> Vec<RenderItem> {
+    let Some(location) = location else {
+        return db.shows().into_iter().flat_map(RenderItem::show).collect();
+    };
+
+    match location.season {
+        Some((season, _)) => render_items(db.episodes(&location.show, Some(season))),
+        None => {
+            let Some(show) = db.get_show(&location.show) else {
+                return vec![];
+            };
+
+            db.seasons(&location.show)
+                .into_iter()
+                .flat_map(|season| RenderItem::season(show, season))
+                .collect()
+        }
+    }
+}
+
+fn mode_items(db: &mut Database, mode: MenuMode, menu: &BrowseMenu) -> Vec<RenderItem> {
+    let (key, order) = menu.sort();
+    match mode {
+        MenuMode::All => render_items(db.sort(key, order)),
+        MenuMode::Random => render_items(db.order_by_random()),
+        MenuMode::Fav => render_items(db.sort_fav(key, order)),
+        MenuMode::Recent => render_items(db.order_by_last_played()),
+        MenuMode::Unwatched => render_items(db.sort_unwatched(key, order)),
+        MenuMode::Shows => show_items(db, menu.show_location()),
+    }
+}
+
+/// An empty query lists the current mode so the cursor can be used right away.
+fn search_items(db: &mut Database, query: &str, menu: &BrowseMenu) -> Vec<RenderItem> {
+    if query.trim().is_empty() {
+        return mode_items(db, menu.mode(), menu);
+    }
+
+    render_items(db.search(query))
+}
+
+fn draw_menu_with_mode(mut menu: BrowseMenu, db: Rc<RefCell<Database>>, mode: MenuMode) {
+    menu.set_item(mode_items(&mut db.borrow_mut(), mode, &menu));
+    menu.draw();
+}
+
+fn draw_menu_with_search(mut menu: BrowseMenu, db: Rc<RefCell<Database>>) {
+    let Some(query) = menu.search_query() else {
+        return;
+    };
+
+    menu.set_search_result(search_items(&mut db.borrow_mut(), &query, &menu));
+    menu.draw();
+}
+
+/// Redraws the current mode or search without moving the cursor.
+fn refresh_menu(mut menu: BrowseMenu, db: Rc<RefCell<Database>>) {
+    let items = match menu.search_query() {
+        Some(query) => search_items(&mut db.borrow_mut(), &query, &menu),
+        None => mode_items(&mut db.borrow_mut(), menu.mode(), &menu),
     };
 
-    menu.set_item(iter.flat_map(|item| item.try_into().ok()).collect());
+    menu.set_item(items);
     menu.draw();
 }
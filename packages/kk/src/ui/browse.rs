@@ -1,6 +1,6 @@
 use enclose::enclose;
 use itertools::Itertools;
-use kr::db::IndexedMovieData;
+use kr::db::{IndexedMovieData, MovieId, ScanProgress, ShowData, SortKey, SortOrder};
 use std::{
     cell::{Cell, RefCell},
     path::PathBuf,
@@ -8,7 +8,7 @@ use std::{
 };
 
 use fltk::{
-    draw,
+    draw::{self, LineStyle},
     enums::{Align, Color, Font, FrameType},
     group::Group,
     image::SharedImage,
@@ -27,12 +27,69 @@ const MENU_IMG_WIDTH: i32 = 312;
 
 const ITEM_GAP: i32 = 8;
 
+const STATUS_LINE_HEIGHT: i32 = 24;
+
 #[derive(Default, Clone, Copy)]
 pub enum MenuMode {
+    /// The whole library in the selected sort order.
     #[default]
-    AddedTime,
+    All,
     Random,
     Fav,
+    Recent,
+    Unwatched,
+    Shows,
+}
+
+impl MenuMode {
+    fn label(&self) -> &'static str {
+        match self {
+            Self::All => "All",
+            Self::Random => "Random",
+            Self::Fav => "Favorites",
+            Self::Recent => "Recently played",
+            Self::Unwatched => "Unwatched",
+            Self::Shows => "TV shows",
+        }
+    }
+
+    /// Random and Recent have an order of their own.
+    pub fn is_sortable(&self) -> bool {
+        matches!(self, Self::All | Self::Fav | Self::Unwatched)
+    }
+}
+
+fn sort_label(key: SortKey, order: SortOrder) -> String {
+    let key = match key {
+        SortKey::AddedTime => "added time",
+        SortKey::ReleaseDate => "release date",
+        SortKey::Title => "title",
+        SortKey::Num => "number",
+        SortKey::LastPlayed => "last played",
+    };
+    let order = match order {
+        SortOrder::Ascending => "asc",
+        SortOrder::Descending => "desc",
+    };
+
+    format!("by {key}, {order}")
+}
+
+/// What a tile opens, a show is keyed by the path of its `tvshow.nfo`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ItemKind {
+    Movie(MovieId),
+    Show(PathBuf),
+    /// Show and season number.
+    Season(PathBuf, u32),
+}
+
+/// The show, and season, the grid is showing in `MenuMode::Shows`.
+#[derive(Clone, Debug)]
+pub struct ShowLocation {
+    pub show: PathBuf,
+    pub title: String,
+    pub season: Option<(u32, String)>,
 }
 
 #[derive(Clone)]
@@ -40,7 +97,45 @@ pub struct RenderItem {
     nfo_path: PathBuf,
     img_path: PathBuf,
     title: String,
-    index: u32,
+    kind: ItemKind,
+    fav: bool,
+}
+
+impl RenderItem {
+    pub fn show(data: &ShowData) -> Option<Self> {
+        let nfo_path = data.path.clone();
+        let img_path = nfo_path.parent()?.join(data.show.thumb_path()?);
+
+        Some(Self {
+            nfo_path,
+            img_path,
+            title: data.show.title.clone(),
+            kind: ItemKind::Show(data.path.clone()),
+            fav: false,
+        })
+    }
+
+    pub fn season(data: &ShowData, season: u32) -> Option<Self> {
+        let nfo_path = data.path.clone();
+        let thumb = data.show.season_thumb_path(season).or(data.show.thumb_path())?;
+        let img_path = nfo_path.parent()?.join(thumb);
+
+        Some(Self {
+            nfo_path,
+            img_path,
+            title: season_title(data, season),
+            kind: ItemKind::Season(data.path.clone(), season),
+            fav: false,
+        })
+    }
+}
+
+pub fn season_title(data: &ShowData, season: u32) -> String {
+    match data.show.season_name(season) {
+        Some(name) => name.to_string(),
+        None if season == 0 => "Specials".to_string(),
+        None => format!("Season {season}"),
+    }
 }
 
 impl TryFrom<IndexedMovieData<'_>> for RenderItem {
@@ -51,13 +146,21 @@ impl TryFrom<IndexedMovieData<'_>> for RenderItem {
         let img_path = nfo_path
             .parent()
             .ok_or(())?
-            .join(value.movie.movie.thumb.clone().ok_or(())?);
+            .join(value.movie.movie.thumb_path().ok_or(())?);
+
+        let title = match value.movie.episode_number() {
+            Some((season, episode)) => {
+                format!("S{season:02}E{episode:02} {}", value.movie.movie.title)
+            }
+            None => value.movie.movie.title.clone(),
+        };
 
         Ok(Self {
             nfo_path,
             img_path,
-            title: value.movie.movie.title.clone(),
-            index: value.index,
+            title,
+            kind: ItemKind::Movie(value.id.clone()),
+            fav: value.movie.fav,
         })
     }
 }
@@ -66,11 +169,12 @@ pub struct MenuItem;
 
 impl MenuItem {
     #[allow(clippy::new_ret_no_self)]
-    pub fn new(item: RenderItem, symbol: String) -> anyhow::Result<Group> {
+    pub fn new(item: RenderItem, symbol: String, selected: bool) -> anyhow::Result<Group> {
         let mut img = SharedImage::load(item.img_path)?;
         img.scale(MENU_IMG_WIDTH, MENU_IMG_HEIGHT, true, true);
 
         let full_txt = item.title;
+        let fav = item.fav;
         let mut draw_img = img.clone();
 
         let mut item = Group::default().with_size(MENU_ITEM_WIDTH, MENU_ITEM_HEIGHT);
@@ -82,6 +186,17 @@ impl MenuItem {
 
             draw_img.draw(img_x, img_y, MENU_IMG_WIDTH, MENU_IMG_HEIGHT);
 
+            if selected {
+                draw::set_draw_color(Color::White);
+                draw::set_line_style(LineStyle::Solid, 3);
+                draw::draw_rect(img_x - 3, w.y() - 3, MENU_IMG_WIDTH + 6, MENU_IMG_HEIGHT + 6);
+                draw::set_line_style(LineStyle::Solid, 0);
+            }
+
+            if fav {
+                draw_star(img_x + MENU_IMG_WIDTH - 18, w.y() + 18, 12.);
+            }
+
             draw::set_draw_color(Color::White);
             draw::set_font(Font::Helvetica, 14);
             let txt_y = img_y + MENU_IMG_HEIGHT + 5 - img_y_fix;
@@ -160,6 +275,38 @@ impl MenuItem {
     }
 }
 
+fn draw_star(cx: i32, cy: i32, r: f64) {
+    let (cx, cy) = (cx as f64, cy as f64);
+    draw::set_draw_color(Color::Yellow);
+    draw::begin_complex_polygon();
+    for i in 0..10 {
+        let r = if i % 2 == 0 { r } else { r * 0.45 };
+        let angle = std::f64::consts::PI * (i as f64 / 5. - 0.5);
+        draw::vertex(cx + r * angle.cos(), cy + r * angle.sin());
+    }
+    draw::end_complex_polygon();
+}
+
+/// Shown in front of the count while the library is scanned in the background.
+fn scan_label(progress: ScanProgress) -> String {
+    if progress.found == 0 {
+        return format!("Scanning, {} folders", progress.dirs);
+    }
+
+    format!("Scanning, {}/{} nfo", progress.parsed + progress.failed, progress.found)
+}
+
+fn draw_status_line(g: &Group, left: &str, right: &str) {
+    let x = g.x() + CONTAINER_MARGIN;
+    let y = g.y() + g.h() - STATUS_LINE_HEIGHT;
+    let w = g.w() - CONTAINER_MARGIN * 2;
+
+    draw::set_draw_color(Color::White);
+    draw::set_font(Font::Helvetica, 16);
+    draw::draw_text2(left, x, y, w, STATUS_LINE_HEIGHT, Align::Left);
+    draw::draw_text2(right, x, y, w, STATUS_LINE_HEIGHT, Align::Right);
+}
+
 #[derive(Clone)]
 pub struct BrowseMenu {
     pub g: Group,
@@ -167,8 +314,13 @@ pub struct BrowseMenu {
     page: Rc<Cell<usize>>,
     symbols: Rc<Vec<String>>,
     symbol: Rc<RefCell<String>>,
-    page_index_list: Rc<RefCell<Vec<u32>>>,
+    page_item_list: Rc<RefCell<Vec<ItemKind>>>,
     mode: Rc<Cell<MenuMode>>,
+    sort: Rc<Cell<(SortKey, SortOrder)>>,
+    show: Rc<RefCell<Option<ShowLocation>>>,
+    search: Rc<RefCell<Option<String>>>,
+    cursor: Rc<Cell<Option<usize>>>,
+    scan: Rc<Cell<Option<ScanProgress>>>,
 }
 
 impl BrowseMenu {
@@ -189,19 +341,49 @@ impl BrowseMenu {
         let symbols = Rc::new(symbols);
         let symbol = Rc::new(RefCell::new(String::from("")));
         let page_path_list = Rc::new(RefCell::new(vec![]));
+        let search: Rc<RefCell<Option<String>>> = Rc::new(RefCell::new(None));
+        let cursor = Rc::new(Cell::new(None));
+        let mode = Rc::new(Cell::new(MenuMode::default()));
+        let sort = Rc::new(Cell::new((SortKey::default(), SortOrder::default())));
+        let show: Rc<RefCell<Option<ShowLocation>>> = Rc::new(RefCell::new(None));
+        let scan: Rc<Cell<Option<ScanProgress>>> = Rc::new(Cell::new(None));
 
         let mut g = Group::default().with_size(width, height).with_pos(0, 0);
 
         g.end();
         g.set_frame(FrameType::NoBox);
 
-        g.draw(|w| {
+        g.draw(enclose!((items, search, mode, sort, show, scan) move |w| {
             draw::draw_rect_fill(w.x(), w.y(), w.w(), w.h(), Color::Black);
             w.draw_children();
-        });
 
-        g.resize_callback(enclose!((items, page, symbols, symbol, page_path_list) move |w, _x, _y, _width, _height| {
-            *page_path_list.borrow_mut() = Self::draw_items(w, &items.borrow(), page.get(), &symbols, &symbol.borrow());
+            let len = items.borrow().len();
+            let count = |unit: &str| match scan.get() {
+                Some(progress) => format!("{}  {len} {unit}", scan_label(progress)),
+                None => format!("{len} {unit}"),
+            };
+
+            if let Some(query) = search.borrow().as_deref() {
+                draw_status_line(w, &format!("/{query}_"), &count("matches"));
+            } else {
+                let mode = mode.get();
+                let label = if mode.is_sortable() {
+                    let (key, order) = sort.get();
+                    format!("{} {}", mode.label(), sort_label(key, order))
+                } else if let Some(location) = show.borrow().as_ref() {
+                    match &location.season {
+                        Some((_, season)) => format!("{} / {} / {season}", mode.label(), location.title),
+                        None => format!("{} / {}", mode.label(), location.title),
+                    }
+                } else {
+                    mode.label().to_string()
+                };
+                draw_status_line(w, &label, &count("movies"));
+            }
+        }));
+
+        g.resize_callback(enclose!((items, page, symbols, symbol, page_path_list, cursor) move |w, _x, _y, _width, _height| {
+            *page_path_list.borrow_mut() = Self::draw_items(w, &items.borrow(), page.get(), &symbols, &symbol.borrow(), cursor.get());
         }));
 
         Self {
@@ -210,18 +392,30 @@ impl BrowseMenu {
             page,
             symbols,
             symbol,
-            page_index_list: page_path_list,
-            mode: Rc::new(Cell::new(MenuMode::default())),
+            page_item_list: page_path_list,
+            mode,
+            sort,
+            show,
+            search,
+            cursor,
+            scan,
         }
     }
 
+    /// Progress of a background scan, `None` once it finished.
+    pub fn set_scan_progress(&mut self, progress: Option<ScanProgress>) {
+        self.scan.set(progress);
+        self.g.redraw();
+    }
+
     pub fn draw(&mut self) {
-        *self.page_index_list.borrow_mut() = Self::draw_items(
+        *self.page_item_list.borrow_mut() = Self::draw_items(
             &mut self.g,
             &self.items.borrow(),
             self.page.get(),
             &self.symbols,
             &self.symbol.borrow(),
+            self.cursor.get(),
         );
     }
 
@@ -231,16 +425,18 @@ impl BrowseMenu {
         page: usize,
         symbols: &[String],
         s: &str,
-    ) -> Vec<u32> {
+        cursor: Option<usize>,
+    ) -> Vec<ItemKind> {
         let page_size = Self::page_size(g);
         let page = page.min(items.len() / page_size + 1);
+        let page_start = page_size * (page.saturating_sub(1));
 
         g.clear();
         g.begin();
 
-        let plist: Vec<u32> = items
+        let plist: Vec<ItemKind> = items
             .iter()
-            .skip(page_size * (page.saturating_sub(1)))
+            .skip(page_start)
             .take(page_size)
             .enumerate()
             .filter_map(|(i, item)| {
@@ -254,14 +450,14 @@ impl BrowseMenu {
                     return None;
                 }
 
-                Some((item, symbol.clone()))
+                Some((item, symbol.clone(), cursor == Some(page_start + i)))
             })
-            .map(|(item, s)| {
-                if MenuItem::new(item.clone(), s).is_err() {
+            .map(|(item, s, selected)| {
+                if MenuItem::new(item.clone(), s, selected).is_err() {
                     println!("{:?} render failed", item.nfo_path);
                 };
 
-                item.index
+                item.kind.clone()
             })
             .collect();
 
@@ -279,14 +475,22 @@ impl BrowseMenu {
         plist
     }
 
+    pub fn mode(&self) -> MenuMode {
+        self.mode.get()
+    }
+
     pub fn next_mode(&self) -> MenuMode {
         use MenuMode::*;
         let mode = match self.mode.get() {
-            AddedTime => Random,
+            All => Random,
             Random => Fav,
-            Fav => AddedTime,
+            Fav => Recent,
+            Recent => Unwatched,
+            Unwatched => Shows,
+            Shows => All,
         };
 
+        self.show.borrow_mut().take();
         self.mode.set(mode);
         mode
     }
@@ -294,16 +498,50 @@ impl BrowseMenu {
     pub fn prev_mode(&self) -> MenuMode {
         use MenuMode::*;
         let mode = match self.mode.get() {
-            AddedTime => Fav,
-            Random => AddedTime,
+            All => Shows,
+            Random => All,
             Fav => Random,
+            Recent => Fav,
+            Unwatched => Recent,
+            Shows => Unwatched,
         };
 
+        self.show.borrow_mut().take();
         self.mode.set(mode);
         mode
     }
 
+    pub fn sort(&self) -> (SortKey, SortOrder) {
+        self.sort.get()
+    }
+
+    pub fn next_sort(&self) -> (SortKey, SortOrder) {
+        use SortKey::*;
+        let (key, order) = self.sort.get();
+        let key = match key {
+            AddedTime => ReleaseDate,
+            ReleaseDate => Title,
+            Title => Num,
+            Num => LastPlayed,
+            LastPlayed => AddedTime,
+        };
+
+        self.sort.set((key, order));
+        (key, order)
+    }
+
+    pub fn reverse_sort(&self) -> (SortKey, SortOrder) {
+        let (key, order) = self.sort.get();
+        self.sort.set((key, order.reverse()));
+        (key, order.reverse())
+    }
+
     pub fn set_item(&mut self, items: Vec<RenderItem>) {
+        // keep the cursor on a valid item when the list shrinks
+        if let Some(cursor) = self.cursor.get() {
+            self.cursor.set(items.len().checked_sub(1).map(|last| cursor.min(last)));
+        }
+
         *self.items.borrow_mut() = items;
     }
 
@@ -335,7 +573,7 @@ impl BrowseMenu {
 
     pub fn page_size(g: &Group) -> usize {
         let h = g.h();
-        let clamp_h = h - CONTAINER_MARGIN * 2;
+        let clamp_h = h - CONTAINER_MARGIN * 2 - STATUS_LINE_HEIGHT;
         let max_h_item_len = clamp_h / MENU_ITEM_HEIGHT;
 
         let w = g.w();
@@ -345,7 +583,108 @@ impl BrowseMenu {
         (max_w_item_len * max_h_item_len) as usize
     }
 
-    pub fn page_first_item_path(&self) -> Option<u32> {
-        self.page_index_list.borrow().first().cloned()
+    pub fn page_first_item(&self) -> Option<ItemKind> {
+        if let Some(cursor) = self.cursor.get() {
+            return self.items.borrow().get(cursor).map(|item| item.kind.clone());
+        }
+
+        self.page_item_list.borrow().first().cloned()
+    }
+
+    pub fn show_location(&self) -> Option<ShowLocation> {
+        self.show.borrow().clone()
+    }
+
+    pub fn open_show(&mut self, show: PathBuf, title: String) {
+        *self.show.borrow_mut() = Some(ShowLocation { show, title, season: None });
+        self.reset_symbol();
+        self.set_page(1);
+    }
+
+    pub fn open_season(&mut self, season: u32, title: String) {
+        if let Some(location) = self.show.borrow_mut().as_mut() {
+            location.season = Some((season, title));
+        }
+        self.reset_symbol();
+        self.set_page(1);
+    }
+
+    /// Goes from the episodes back to the seasons and from there to the
+    /// shows, returns false when there is no level to leave.
+    pub fn close_show_level(&mut self) -> bool {
+        let mut show = self.show.borrow_mut();
+        let Some(location) = show.as_mut() else {
+            return false;
+        };
+
+        if location.season.take().is_none() {
+            *show = None;
+        }
+        drop(show);
+
+        self.reset_symbol();
+        self.set_page(1);
+        true
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.search.borrow().is_some()
+    }
+
+    pub fn search_query(&self) -> Option<String> {
+        self.search.borrow().clone()
+    }
+
+    pub fn start_search(&self) {
+        self.search.borrow_mut().get_or_insert_default();
+        self.reset_symbol();
+    }
+
+    pub fn end_search(&mut self) {
+        *self.search.borrow_mut() = None;
+        self.cursor.set(None);
+        self.set_page(1);
+    }
+
+    pub fn push_search_text(&self, text: &str) {
+        if let Some(query) = self.search.borrow_mut().as_mut() {
+            query.push_str(text);
+        }
+    }
+
+    pub fn pop_search_char(&self) {
+        if let Some(query) = self.search.borrow_mut().as_mut() {
+            query.pop();
+        }
+    }
+
+    /// Shows the search results starting from the first match.
+    pub fn set_search_result(&mut self, items: Vec<RenderItem>) {
+        self.cursor.set((!items.is_empty()).then_some(0));
+        self.set_item(items);
+        self.set_page(1);
+    }
+
+    pub fn select_next(&mut self) {
+        let len = self.items.borrow().len();
+        if let Some(cursor) = self.cursor.get()
+            && cursor + 1 < len
+        {
+            self.select(cursor + 1);
+        }
+    }
+
+    pub fn select_prev(&mut self) {
+        if let Some(cursor) = self.cursor.get()
+            && cursor > 0
+        {
+            self.select(cursor - 1);
+        }
+    }
+
+    /// Moves the cursor and turns to the page it's on.
+    fn select(&mut self, cursor: usize) {
+        self.cursor.set(Some(cursor));
+        self.set_page(cursor / Self::page_size(&self.g).max(1) + 1);
     }
 }
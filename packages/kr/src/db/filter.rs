@@ -0,0 +1,193 @@
+use std::ops::RangeInclusive;
+
+use ahash::AHashMap;
+
+use crate::{
+    Movie,
+    db::{MovieData, release_date},
+};
+
+/// Composable filter over the nfo metadata, e.g.
+/// `Filter::actor("X").and(Filter::genre("Y")).and(Filter::tag("Z").not())`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Filter {
+    All,
+    Actor(String),
+    Genre(String),
+    Tag(String),
+    Label(String),
+    Year(u32),
+    /// Release years from the first to the second, both included.
+    Years(u32, u32),
+    /// Default rating between the bounds, both included, in tenths of a point
+    /// so the filter can be hashed.
+    Rating(u32, u32),
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+    Not(Box<Filter>),
+}
+
+impl Filter {
+    pub fn actor(name: impl Into<String>) -> Self {
+        Self::Actor(name.into())
+    }
+
+    pub fn genre(genre: impl Into<String>) -> Self {
+        Self::Genre(genre.into())
+    }
+
+    pub fn tag(tag: impl Into<String>) -> Self {
+        Self::Tag(tag.into())
+    }
+
+    pub fn label(label: impl Into<String>) -> Self {
+        Self::Label(label.into())
+    }
+
+    pub fn year(year: u32) -> Self {
+        Self::Year(year)
+    }
+
+    pub fn years(years: RangeInclusive<u32>) -> Self {
+        Self::Years(*years.start(), *years.end())
+    }
+
+    /// Ratings are rounded to a tenth, `Filter::rating(7.5..=10.)`.
+    pub fn rating(rating: RangeInclusive<f32>) -> Self {
+        Self::Rating(tenths(*rating.start()), tenths(*rating.end()))
+    }
+
+    /// Narrows the filter, `All.and(f)` is just `f`.
+    pub fn and(self, other: Filter) -> Self {
+        match self {
+            Self::All => other,
+            Self::And(mut list) => {
+                list.push(other);
+                Self::And(list)
+            }
+            filter => Self::And(vec![filter, other]),
+        }
+    }
+
+    pub fn or(self, other: Filter) -> Self {
+        match self {
+            Self::Or(mut list) => {
+                list.push(other);
+                Self::Or(list)
+            }
+            filter => Self::Or(vec![filter, other]),
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(self) -> Self {
+        Self::Not(Box::new(self))
+    }
+
+    /// The filter without its last `And` term, results of the parent can be
+    /// narrowed instead of scanning the whole library.
+    pub fn parent(&self) -> Option<Filter> {
+        let Self::And(list) = self else {
+            return None;
+        };
+
+        match list.len() {
+            0 | 1 => None,
+            2 => Some(list[0].clone()),
+            n => Some(Self::And(list[..n - 1].to_vec())),
+        }
+    }
+
+    pub fn matches(&self, data: &MovieData) -> bool {
+        let movie = &data.movie;
+        match self {
+            Self::All => true,
+            Self::Actor(name) => movie.actor.iter().any(|a| a.name == *name),
+            Self::Genre(genre) => contains(&movie.genre, genre),
+            Self::Tag(tag) => contains(&movie.tag, tag),
+            Self::Label(label) => movie.label.as_ref() == Some(label),
+            Self::Year(year) => release_year(movie) == Some(*year),
+            Self::Years(from, to) => release_year(movie).is_some_and(|y| (*from..=*to).contains(&y)),
+            Self::Rating(min, max) => movie
+                .default_rating()
+                .is_some_and(|r| (*min..=*max).contains(&tenths(r))),
+            Self::And(list) => list.iter().all(|f| f.matches(data)),
+            Self::Or(list) => list.iter().any(|f| f.matches(data)),
+            Self::Not(filter) => !filter.matches(data),
+        }
+    }
+}
+
+#[inline]
+fn tenths(rating: f32) -> u32 {
+    (rating.max(0.) * 10.).round() as u32
+}
+
+#[inline]
+fn contains(list: &Option<Vec<String>>, value: &str) -> bool {
+    list.as_ref()
+        .map(|list| list.iter().any(|v| v == value))
+        .unwrap_or(false)
+}
+
+/// Year of the release date, see [`release_date`].
+pub fn release_year(movie: &Movie) -> Option<u32> {
+    release_date(movie).map(|(year, _, _)| year)
+}
+
+/// Number of movies per value in a result set, most common value first.
+#[derive(Clone, Debug, Default)]
+pub struct Facets {
+    pub actor: Vec<(String, usize)>,
+    pub genre: Vec<(String, usize)>,
+    pub tag: Vec<(String, usize)>,
+    pub label: Vec<(String, usize)>,
+    pub year: Vec<(u32, usize)>,
+}
+
+impl Facets {
+    pub fn new<'a>(movies: impl Iterator<Item = &'a MovieData>) -> Self {
+        let mut actor = AHashMap::new();
+        let mut genre = AHashMap::new();
+        let mut tag = AHashMap::new();
+        let mut label = AHashMap::new();
+        let mut year = AHashMap::new();
+
+        for data in movies {
+            let movie = &data.movie;
+            for a in &movie.actor {
+                *actor.entry(a.name.clone()).or_default() += 1;
+            }
+
+            for g in movie.genre.iter().flatten() {
+                *genre.entry(g.clone()).or_default() += 1;
+            }
+
+            for t in movie.tag.iter().flatten() {
+                *tag.entry(t.clone()).or_default() += 1;
+            }
+
+            if let Some(l) = &movie.label {
+                *label.entry(l.clone()).or_default() += 1;
+            }
+
+            if let Some(y) = release_year(movie) {
+                *year.entry(y).or_default() += 1;
+            }
+        }
+
+        Self {
+            actor: sorted(actor),
+            genre: sorted(genre),
+            tag: sorted(tag),
+            label: sorted(label),
+            year: sorted(year),
+        }
+    }
+}
+
+fn sorted<T: Ord>(counts: AHashMap<T, usize>) -> Vec<(T, usize)> {
+    let mut list: Vec<(T, usize)> = counts.into_iter().collect();
+    list.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    list
+}
@@ -0,0 +1,81 @@
+use std::fmt;
+
+use ahash::AHashSet;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::Movie;
+
+/// Stable key of a library entry. Unlike its position in `Config.movies` it
+/// survives rescans, pruning and moves of the nfo file.
+///
+/// Taken from the nfo `uniqueid` or `num` when there is one, e.g. `imdb:tt0111161`
+/// or `num:ABC-123`, otherwise a random UUID.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(transparent)]
+pub struct MovieId(String);
+
+impl MovieId {
+    /// The default `uniqueid` of the nfo, then its first one, then `num`.
+    pub fn from_nfo(movie: &Movie) -> Option<Self> {
+        let uniqueids = movie
+            .uniqueid
+            .iter()
+            .flatten()
+            .map(|id| (id.kind.as_deref(), id.default == Some(true), id.value.as_str()));
+
+        Self::from_parts(uniqueids, movie.num.as_deref())
+    }
+
+    /// `uniqueids` are `(type, default, value)` triples.
+    pub(crate) fn from_parts<'a>(
+        uniqueids: impl Iterator<Item = (Option<&'a str>, bool, &'a str)> + Clone,
+        num: Option<&str>,
+    ) -> Option<Self> {
+        let uniqueids = uniqueids.filter(|(_, _, value)| !value.trim().is_empty());
+        let uniqueid = uniqueids
+            .clone()
+            .find(|(_, default, _)| *default)
+            .or_else(|| uniqueids.clone().next());
+
+        if let Some((kind, _, value)) = uniqueid {
+            let kind = kind.map(str::trim).filter(|k| !k.is_empty()).unwrap_or("unknown");
+            return Some(Self(format!("{}:{}", kind.to_lowercase(), value.trim())));
+        }
+
+        num.map(str::trim)
+            .filter(|num| !num.is_empty())
+            .map(|num| Self(format!("num:{num}")))
+    }
+
+    /// An id as it was stored before.
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn from_stored(id: String) -> Self {
+        Self(id)
+    }
+
+    pub fn random() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+
+    /// `id` unless another entry already uses it, then a random one.
+    pub(crate) fn claim(id: Option<Self>, used: &mut AHashSet<MovieId>) -> Self {
+        let id = id
+            .filter(|id| !used.contains(id))
+            .unwrap_or_else(Self::random);
+
+        used.insert(id.clone());
+        id
+    }
+
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for MovieId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
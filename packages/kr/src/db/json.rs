@@ -0,0 +1,214 @@
+use std::{
+    ffi::OsString,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use log::warn;
+
+use crate::db::{Config, MovieId, NewerVersion, Storage, migrate};
+
+const BACKUP_COUNT: usize = 3;
+// the file is flushed on every change, don't turn every flush into a backup
+const BACKUP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Stores the whole library as a single `kr.json`.
+///
+/// Writes go to a temporary file that is renamed over `kr.json`, so a crash
+/// never leaves a truncated file behind. The previous versions are kept as
+/// `kr.json.1` (newest) to `kr.json.3` and used when `kr.json` is unreadable.
+/// An unreadable `kr.json` is not overwritten but moved aside to
+/// `kr.json.unreadable-<unix time>`, it may come from a newer version or be
+/// worth recovering.
+#[derive(Debug)]
+pub struct JsonStorage {
+    path: PathBuf,
+    // mtime and size of `path` when it was last known to be readable
+    readable: Mutex<Option<(SystemTime, u64)>>,
+}
+
+impl JsonStorage {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            readable: Mutex::new(None),
+        }
+    }
+
+    #[inline]
+    pub fn default_path() -> PathBuf {
+        dirs::DIR.config_local_dir().join("kr.json")
+    }
+
+    fn with_suffix(&self, suffix: &str) -> PathBuf {
+        let mut name = OsString::from(self.path.as_os_str());
+        name.push(suffix);
+        PathBuf::from(name)
+    }
+
+    fn backup_path(&self, n: usize) -> PathBuf {
+        self.with_suffix(&format!(".{n}"))
+    }
+
+    fn read(path: &Path) -> Result<Config> {
+        let content = std::fs::read_to_string(path)?;
+        migrate(serde_json::from_str(&content)?)
+    }
+
+    fn file_state(&self) -> Option<(SystemTime, u64)> {
+        let metadata = std::fs::metadata(&self.path).ok()?;
+        Some((metadata.modified().ok()?, metadata.len()))
+    }
+
+    fn mark_readable(&self) {
+        *self.readable.lock().unwrap() = self.file_state();
+    }
+
+    /// Moves the current file out of the way when it can't be read, so the next
+    /// write neither replaces it nor rotates it into the backups. The file is
+    /// only read again when it changed since it was last known to be readable.
+    fn set_aside_unreadable(&self) -> Result<()> {
+        let Some(state) = self.file_state() else {
+            return Ok(());
+        };
+
+        if *self.readable.lock().unwrap() == Some(state) {
+            return Ok(());
+        }
+
+        let Err(e) = Self::read(&self.path) else {
+            return Ok(());
+        };
+
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let aside = self.with_suffix(&format!(".unreadable-{time}"));
+        warn!("{:?} is unreadable, moved to {aside:?}: {e}", self.path);
+        std::fs::rename(&self.path, aside)?;
+        Ok(())
+    }
+
+    /// Moves the current file into the backup slots, unless the newest backup
+    /// is recent. Unreadable files are [set aside](Self::set_aside_unreadable)
+    /// before.
+    fn rotate_backups(&self) -> Result<()> {
+        if !self.path.exists() {
+            return Ok(());
+        }
+
+        let newest = self.backup_path(1);
+        let is_recent = std::fs::metadata(&newest)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.elapsed().ok())
+            .map(|age| age < BACKUP_INTERVAL)
+            .unwrap_or(false);
+
+        if is_recent {
+            return Ok(());
+        }
+
+        for n in (1..BACKUP_COUNT).rev() {
+            let from = self.backup_path(n);
+            if from.exists() {
+                std::fs::rename(from, self.backup_path(n + 1))?;
+            }
+        }
+
+        std::fs::rename(&self.path, newest)?;
+        // keep the backup age based on when it was taken
+        File::options()
+            .write(true)
+            .open(self.backup_path(1))?
+            .set_modified(SystemTime::now())?;
+        Ok(())
+    }
+}
+
+impl Default for JsonStorage {
+    fn default() -> Self {
+        Self::new(Self::default_path())
+    }
+}
+
+impl Storage for JsonStorage {
+    fn load(&self) -> Result<Option<Config>> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let error = if self.path.exists() {
+            match Self::read(&self.path) {
+                Ok(config) => {
+                    self.mark_readable();
+                    return Ok(Some(config));
+                }
+                // an older backup would lose what the newer version added
+                Err(e) if e.is::<NewerVersion>() => return Err(e),
+                Err(e) => Some(e),
+            }
+        } else {
+            None
+        };
+
+        // the main file is corrupt or a crash happened right after rotating it away
+        for n in 1..=BACKUP_COUNT {
+            let backup = self.backup_path(n);
+            if !backup.exists() {
+                continue;
+            }
+
+            match Self::read(&backup) {
+                Ok(config) => {
+                    warn!("{:?} is unreadable, restored from {backup:?}", self.path);
+                    return Ok(Some(config));
+                }
+                Err(e) => warn!("{backup:?} is unreadable: {e}"),
+            }
+        }
+
+        match error {
+            Some(e) => Err(e),
+            None => Ok(None),
+        }
+    }
+
+    fn update(&self, config: &Config, _id: &MovieId) -> Result<()> {
+        // a single entry can't be written without rewriting the file
+        self.flush(config)
+    }
+
+    fn flush(&self, config: &Config) -> Result<()> {
+        let tmp_path = self.with_suffix(".tmp");
+        let mut tmp = File::create(&tmp_path)?;
+        serde_json::to_writer(&mut tmp, config)?;
+        tmp.flush()?;
+        tmp.sync_all()?;
+        drop(tmp);
+
+        // never replace a file that might be the only copy of a library
+        self.set_aside_unreadable()?;
+        if let Err(e) = self.rotate_backups() {
+            warn!("{:?} backup failed: {e}", self.path);
+        }
+
+        std::fs::rename(&tmp_path, &self.path)?;
+        self.mark_readable();
+        sync_dir(&self.path)
+    }
+}
+
+/// Makes the renames in the directory of `path` durable, the data of a renamed
+/// file is synced but its new name may still be lost on a power cut.
+fn sync_dir(path: &Path) -> Result<()> {
+    // directories can't be opened as files on windows
+    if cfg!(unix) {
+        let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        File::open(dir)?.sync_all()?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,199 @@
+use std::fmt;
+
+use ahash::AHashSet;
+use anyhow::{Result, anyhow, bail};
+use serde_json::{Map, Value, json};
+
+use crate::{
+    db::{Config, MovieId},
+    video,
+};
+
+/// Schema version written by this build.
+///
+/// Bump it together with a new step in `MIGRATIONS` whenever the stored
+/// shape of `Config` or `MovieData` changes.
+pub const SCHEMA_VERSION: u32 = 7;
+
+type Migration = fn(&mut Map<String, Value>) -> Result<()>;
+
+/// A stored config written by a newer build. It can't be read, and must not
+/// be replaced either, the newer build may still want it.
+#[derive(Debug)]
+pub struct NewerVersion(pub u32);
+
+impl fmt::Display for NewerVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "schema version {} is newer than the supported version {SCHEMA_VERSION}", self.0)
+    }
+}
+
+impl std::error::Error for NewerVersion {}
+
+/// `MIGRATIONS[n]` upgrades a config from version `n` to `n + 1`.
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [v0_to_v1, v1_to_v2, v2_to_v3, v3_to_v4, v4_to_v5, v5_to_v6, v6_to_v7];
+
+/// Upgrades a stored config of any older version to the current schema.
+/// Files without a `version` field predate versioning and count as version 0,
+/// files of a newer version fail with [`NewerVersion`].
+pub fn migrate(value: Value) -> Result<Config> {
+    let Value::Object(mut config) = value else {
+        bail!("stored config is not an object");
+    };
+
+    let version = match config.get("version") {
+        Some(v) => v
+            .as_u64()
+            .ok_or_else(|| anyhow!("invalid schema version {v}"))? as u32,
+        None => 0,
+    };
+
+    if version > SCHEMA_VERSION {
+        bail!(NewerVersion(version));
+    }
+
+    for (from, step) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        step(&mut config).map_err(|e| e.context(format!("migrating schema version {from}")))?;
+    }
+
+    config.insert("version".to_string(), SCHEMA_VERSION.into());
+    Ok(serde_json::from_value(Value::Object(config))?)
+}
+
+fn movies_mut(config: &mut Map<String, Value>) -> impl Iterator<Item = &mut Map<String, Value>> {
+    config
+        .get_mut("movies")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_object_mut)
+}
+
+fn roots_mut(config: &mut Map<String, Value>) -> impl Iterator<Item = &mut Map<String, Value>> {
+    config
+        .get_mut("roots")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_object_mut)
+}
+
+#[inline]
+fn set_default(obj: &mut Map<String, Value>, key: &str, value: Value) {
+    obj.entry(key).or_insert(value);
+}
+
+/// Library roots replace the single global scan time, and movies gain
+/// markers, resume position, watch history and nfo change tracking.
+fn v0_to_v1(config: &mut Map<String, Value>) -> Result<()> {
+    config.remove("last_scan_time");
+    set_default(config, "roots", json!([]));
+    set_default(config, "movies", json!([]));
+
+    for movie in movies_mut(config) {
+        set_default(movie, "root", json!(""));
+        set_default(movie, "nfo_mtime", Value::Null);
+        set_default(movie, "markers", json!([]));
+        set_default(movie, "resume_pos", Value::Null);
+        set_default(movie, "last_played", Value::Null);
+        set_default(movie, "play_count", json!(0));
+        set_default(movie, "watched", json!(false));
+    }
+
+    Ok(())
+}
+
+/// `thumb` holds every `<thumb>` of Kodi nfo files and `fanart` may list
+/// several images, a plain file name becomes the only entry.
+fn v1_to_v2(config: &mut Map<String, Value>) -> Result<()> {
+    for data in movies_mut(config) {
+        let Some(movie) = data.get_mut("movie").and_then(Value::as_object_mut) else {
+            continue;
+        };
+
+        let thumb = match movie.remove("thumb") {
+            Some(Value::String(path)) => json!([{ "$text": path }]),
+            Some(Value::Array(list)) => Value::Array(list),
+            _ => json!([]),
+        };
+        movie.insert("thumb".to_string(), thumb);
+
+        if let Some(Value::String(path)) = movie.get("fanart") {
+            let fanart = json!({ "$text": path });
+            movie.insert("fanart".to_string(), fanart);
+        }
+    }
+
+    Ok(())
+}
+
+/// TV shows are stored next to the movies, episodes point to their show.
+fn v2_to_v3(config: &mut Map<String, Value>) -> Result<()> {
+    set_default(config, "shows", json!([]));
+
+    for movie in movies_mut(config) {
+        set_default(movie, "show", Value::Null);
+    }
+
+    Ok(())
+}
+
+/// Roots choose how their nfo files are discovered, the default keeps the
+/// Kodi folder layout that was the only option before.
+fn v3_to_v4(config: &mut Map<String, Value>) -> Result<()> {
+    for root in roots_mut(config) {
+        set_default(root, "discovery", json!({ "strategy": "KodiFolder", "include": [], "exclude": [] }));
+    }
+
+    Ok(())
+}
+
+/// Roots remember the directories of their last scan, an empty state makes
+/// the next scan read everything once.
+fn v4_to_v5(config: &mut Map<String, Value>) -> Result<()> {
+    for root in roots_mut(config) {
+        set_default(root, "dirs", json!({}));
+    }
+
+    Ok(())
+}
+
+/// Movies are keyed by a stable id instead of their position, taken from the
+/// nfo `uniqueid` or `num` like for newly found movies.
+fn v5_to_v6(config: &mut Map<String, Value>) -> Result<()> {
+    let mut ids = AHashSet::new();
+
+    for data in movies_mut(config) {
+        let movie = data.get("movie");
+        let uniqueids = movie
+            .and_then(|m| m.get("uniqueid"))
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| {
+                Some((
+                    id.get("@type").and_then(Value::as_str),
+                    id.get("@default").and_then(Value::as_bool).unwrap_or(false),
+                    id.get("$text").and_then(Value::as_str)?,
+                ))
+            });
+        let num = movie.and_then(|m| m.get("num")).and_then(Value::as_str);
+
+        let id = MovieId::claim(MovieId::from_parts(uniqueids, num), &mut ids);
+        data.insert("id".to_string(), json!(id));
+    }
+
+    Ok(())
+}
+
+/// Video files are found at scan time and stored with each movie, empty lists
+/// make the next scan look for them.
+fn v6_to_v7(config: &mut Map<String, Value>) -> Result<()> {
+    set_default(config, "video_extensions", json!(video::DEFAULT_EXTENSIONS));
+
+    for movie in movies_mut(config) {
+        set_default(movie, "videos", json!([]));
+    }
+
+    Ok(())
+}
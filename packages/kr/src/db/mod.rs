@@ -0,0 +1,1493 @@
+use log::{info, warn};
+use ahash::{AHashMap, AHashSet};
+use anyhow::{Result, bail};
+use rand::seq::SliceRandom;
+use rand::rng;
+use rayon::prelude::*;
+use std::{
+    ffi::{OsStr, OsString}, path::{Path, PathBuf}, time::SystemTime
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Movie, TvShow,
+    db::scan::ScanTracker,
+    discovery::Discovery,
+    util::{DirStates, NfoKind, find_new_nfo, find_new_nfo_with_progress},
+    video::{self, find_videos},
+};
+
+mod filter;
+mod id;
+mod json;
+mod migration;
+mod scan;
+mod search;
+mod sort;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod storage;
+
+pub use filter::{Facets, Filter, release_year};
+pub use id::MovieId;
+pub use json::JsonStorage;
+pub use migration::{NewerVersion, SCHEMA_VERSION, migrate};
+pub use scan::{BackgroundScan, ParseFailure, ScanProgress, ScanReport, ScanResult};
+pub use search::{SearchIndex, normalize, tokenize};
+pub use sort::{SortKey, SortOrder, natural_cmp, release_date};
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStorage;
+pub use storage::{Storage, StorageQuery, import};
+
+const MARKER_TOLERANCE: f64 = 0.5;
+// positions this close to either end of the movie are not worth resuming from
+const RESUME_MIN_POS: f64 = 5.;
+const RESUME_END_MARGIN: f64 = 10.;
+// share of the duration after which a movie counts as watched
+const WATCHED_RATIO: f64 = 0.9;
+// cached filter results are dropped once there are more than this
+const FILTER_CACHE_LIMIT: usize = 64;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Config {
+    pub version: u32,
+    pub movies: Vec<MovieData>,
+    pub roots: Vec<LibraryRoot>,
+    pub shows: Vec<ShowData>,
+    /// Extensions of the video files looked for next to each nfo, in order of preference.
+    pub video_extensions: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            version: SCHEMA_VERSION,
+            movies: Default::default(),
+            roots: Default::default(),
+            shows: Default::default(),
+            video_extensions: video::DEFAULT_EXTENSIONS.map(String::from).to_vec(),
+        }
+    }
+}
+
+impl Config {
+    fn root(&self, path: &Path) -> Option<&LibraryRoot> {
+        self.roots.iter().find(|r| r.path == path)
+    }
+
+    /// Makes the next scan read every directory and re-parse every nfo.
+    fn forget_scan_state(&mut self) {
+        for root in &mut self.roots {
+            root.dirs.clear();
+        }
+
+        for item in &mut self.movies {
+            item.nfo_mtime = None;
+            item.videos.clear();
+        }
+
+        for show in &mut self.shows {
+            show.nfo_mtime = None;
+        }
+    }
+
+    /// Registers the roots that are not known yet.
+    fn add_roots(&mut self, roots: &[PathBuf]) {
+        for root in roots {
+            if self.root(root).is_none() {
+                self.roots.push(LibraryRoot::new(root.clone()));
+            }
+        }
+    }
+
+    fn is_root_enabled(&self, path: &Path) -> bool {
+        self.root(path).map(|r| r.enabled).unwrap_or(true)
+    }
+
+    /// The enabled roots containing one of `paths`.
+    fn changed_roots(&self, paths: &[PathBuf]) -> Vec<PathBuf> {
+        self.roots
+            .iter()
+            .filter(|r| r.enabled && paths.iter().any(|p| p.starts_with(&r.path)))
+            .map(|r| r.path.clone())
+            .collect()
+    }
+
+    /// Entries of a disabled or unmounted root are kept as they are.
+    fn is_root_available(&self, path: &Path) -> bool {
+        self.root(path)
+            .map(|r| r.enabled && r.path.is_dir())
+            .unwrap_or(false)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LibraryRoot {
+    pub path: PathBuf,
+    pub last_scan_time: SystemTime,
+    pub enabled: bool,
+    pub discovery: Discovery,
+    pub dirs: DirStates,
+}
+
+impl LibraryRoot {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            last_scan_time: SystemTime::UNIX_EPOCH,
+            enabled: true,
+            discovery: Discovery::default(),
+            dirs: DirStates::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct IndexCacheTable {
+    pub idx: Option<Vec<u32>>,
+    pub dirty: bool,
+}
+
+impl Default for IndexCacheTable {
+    fn default() -> Self {
+        Self {
+            idx: None,
+            dirty: true
+        }
+    }
+}
+
+/// A show parsed from its `tvshow.nfo`, the episodes are entries of
+/// `Config::movies` that point back to it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ShowData {
+    pub path: PathBuf,
+    pub show: TvShow,
+    pub root: PathBuf,
+    pub nfo_mtime: Option<SystemTime>,
+}
+
+impl ShowData {
+    fn load(path: &Path, root: &Path) -> Result<Self> {
+        let nfo_mtime = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        let show = TvShow::load(path).inspect_err(|e| warn!("{path:?} nfo parse failed: {e}"))?;
+
+        Ok(Self {
+            path: path.to_owned(),
+            show,
+            root: root.to_owned(),
+            nfo_mtime,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MovieData {
+    pub id: MovieId,
+    pub path: PathBuf,
+    pub movie: Movie,
+    #[serde(default)]
+    pub root: PathBuf,
+    /// The `tvshow.nfo` of the show this episode belongs to.
+    #[serde(default)]
+    pub show: Option<PathBuf>,
+    #[serde(default)]
+    pub nfo_mtime: Option<SystemTime>,
+    /// Video files next to the nfo in playing order, several for a multi-part
+    /// release. Empty when none was found.
+    #[serde(default)]
+    pub videos: Vec<PathBuf>,
+    pub added_time: SystemTime,
+    pub fav: bool,
+    #[serde(default)]
+    pub markers: Vec<f64>,
+    #[serde(default)]
+    pub resume_pos: Option<f64>,
+    #[serde(default)]
+    pub last_played: Option<SystemTime>,
+    #[serde(default)]
+    pub play_count: u32,
+    #[serde(default)]
+    pub watched: bool,
+}
+
+impl MovieData {
+    pub fn is_episode(&self) -> bool {
+        self.show.is_some()
+    }
+
+    /// Season and episode number from the nfo or a `S01E02` file name,
+    /// an episode without a season belongs to season 1.
+    pub fn episode_number(&self) -> Option<(u32, u32)> {
+        if !self.is_episode() {
+            return None;
+        }
+
+        if let Some(episode) = self.movie.episode {
+            return Some((self.movie.season.unwrap_or(1), episode));
+        }
+
+        let stem = self.path.file_stem()?.to_string_lossy().to_lowercase();
+        stem.match_indices('s').find_map(|(i, _)| {
+            let rest = &stem[i + 1..];
+            let season_len = rest.find(|c: char| !c.is_ascii_digit())?;
+            let season = rest[..season_len].parse().ok()?;
+            let rest = rest[season_len..].strip_prefix('e')?;
+            let episode_len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+            Some((season, rest[..episode_len].parse().ok()?))
+        })
+    }
+
+    /// Whether `other` is this entry after it was moved or renamed, for entries
+    /// whose nfo gives no id to match them by. Either its folder was renamed
+    /// and the title is the same, `renamed_dir` tells whether the folder of
+    /// `other` has the inode the old one had, or one of the `videos` of `other`
+    /// has the name of an old one. Names like `movie.mkv` are shared by many
+    /// movies, a video name only counts when the title or year is the same too
+    /// or when `is_unique` says no other old or new entry has a video of that name.
+    fn is_same_movie(
+        &self,
+        other: &MovieData,
+        renamed_dir: bool,
+        videos: &[PathBuf],
+        is_unique: impl Fn(&OsStr) -> bool,
+    ) -> bool {
+        // entries with different ids in their nfo are different movies
+        if let Some(id) = MovieId::from_nfo(&self.movie)
+            && MovieId::from_nfo(&other.movie).is_some_and(|other_id| other_id != id)
+        {
+            return false;
+        }
+
+        let same_title = self.movie.title == other.movie.title;
+        // a deleted folder may hand its inode to a new one
+        if renamed_dir && same_title {
+            return true;
+        }
+
+        let year = release_year(&self.movie);
+        let same_year = year.is_some() && year == release_year(&other.movie);
+        self.videos
+            .iter()
+            .filter_map(|old| old.file_name())
+            .filter(|name| videos.iter().any(|video| video.file_name() == Some(name)))
+            .any(|name| same_title || same_year || is_unique(name))
+    }
+
+    /// Carries the id and the user owned fields of `old` over to this entry.
+    fn inherit_user_data(&mut self, old: MovieData) {
+        self.id = old.id;
+        self.added_time = old.added_time;
+        self.fav = old.fav;
+        self.markers = old.markers;
+        self.resume_pos = old.resume_pos;
+        self.last_played = old.last_played;
+        self.play_count = old.play_count;
+        self.watched = old.watched;
+    }
+}
+
+/// How often each file name occurs in `paths`.
+fn count_file_names<'a>(paths: impl Iterator<Item = &'a PathBuf>) -> AHashMap<OsString, usize> {
+    let mut names = AHashMap::new();
+    for name in paths.filter_map(|path| path.file_name()) {
+        *names.entry(name.to_owned()).or_default() += 1;
+    }
+    names
+}
+
+/// The library kept in memory with its cached orderings, persisted through a
+/// [`Storage`] backend, `kr.json` by default.
+#[derive(Debug)]
+pub struct Database {
+    config: Config,
+    storage: Box<dyn Storage>,
+    positions: AHashMap<MovieId, u32>,
+    index_ref: Vec<u32>,
+    order_by_fav_index: IndexCacheTable,
+    order_by_random_index: IndexCacheTable,
+    order_by_last_played_index: IndexCacheTable,
+    filter_by_unwatched_index: IndexCacheTable,
+    query_index: IndexCacheTable,
+    search_index: Option<SearchIndex>,
+    search_result_index: IndexCacheTable,
+    episode_index: IndexCacheTable,
+    sorted_subset_index: IndexCacheTable,
+    search_query: String,
+    filter_index: AHashMap<Filter, IndexCacheTable>,
+    sort_index: AHashMap<(SortKey, SortOrder), IndexCacheTable>,
+    facets: AHashMap<Filter, Facets>,
+    scan_report: ScanReport,
+    // the stored library could not be loaded, writing would replace it
+    read_only: bool,
+}
+
+/// The name [`Database`] had while it could only store `kr.json`.
+#[deprecated(note = "renamed to `Database`")]
+pub type SimpleJsonDatabase = Database;
+
+impl Default for Database {
+    fn default() -> Self {
+        Self::with_storage(Box::new(JsonStorage::default()))
+    }
+}
+
+impl Database {
+    pub fn with_storage(storage: Box<dyn Storage>) -> Self {
+        let config = Config::default();
+        let index_ref = (0..config.movies.len() as u32).collect();
+
+        Self {
+            config,
+            storage,
+            positions: AHashMap::new(),
+            index_ref,
+            order_by_fav_index: IndexCacheTable::default(),
+            order_by_random_index: IndexCacheTable::default(),
+            order_by_last_played_index: IndexCacheTable::default(),
+            filter_by_unwatched_index: IndexCacheTable::default(),
+            query_index: IndexCacheTable::default(),
+            search_index: None,
+            search_result_index: IndexCacheTable::default(),
+            episode_index: IndexCacheTable::default(),
+            sorted_subset_index: IndexCacheTable::default(),
+            search_query: String::new(),
+            filter_index: AHashMap::new(),
+            sort_index: AHashMap::new(),
+            facets: AHashMap::new(),
+            scan_report: ScanReport::default(),
+            read_only: false,
+        }
+    }
+
+    /// Loads the cached library, registers `roots` that are not known yet and
+    /// scans every enabled root for changes.
+    pub fn load_config(&mut self, roots: &[PathBuf]) -> Result<()> {
+        self.load_config_with_progress(roots, |_| {})
+    }
+
+    /// [`load_config`](Self::load_config) that reports the progress of the
+    /// scan, `progress` is called from the threads parsing nfo files.
+    pub fn load_config_with_progress(
+        &mut self,
+        roots: &[PathBuf],
+        progress: impl Fn(ScanProgress) + Sync,
+    ) -> Result<()> {
+        let loaded = self.load_with_progress(roots, progress);
+        self.read_only = loaded.is_err();
+        (self.config, self.scan_report) = loaded?;
+        self.reset_index();
+        self.flush()
+    }
+
+    /// Nfo files of the last scan that could not be parsed are collected in the
+    /// report instead of failing the whole load.
+    pub fn load(&self, roots: &[PathBuf]) -> Result<(Config, ScanReport)> {
+        self.load_with_progress(roots, |_| {})
+    }
+
+    pub fn load_with_progress(
+        &self,
+        roots: &[PathBuf],
+        progress: impl Fn(ScanProgress) + Sync,
+    ) -> Result<(Config, ScanReport)> {
+        let mut config = self.storage.load()?.unwrap_or_default();
+        config.add_roots(roots);
+
+        let report = Self::scan(&mut config, |_| true, &progress);
+        Ok((config, report))
+    }
+
+    /// Loads the cached library without scanning, so it can be shown right away.
+    /// Scan it with [`background_scan`](Self::background_scan) afterwards.
+    ///
+    /// When the stored library can't be loaded, e.g. it was written by a newer
+    /// version, the database turns [read-only](Self::is_read_only) instead of
+    /// replacing it with an empty one.
+    pub fn load_cached(&mut self, roots: &[PathBuf]) -> Result<()> {
+        let loaded = self.storage.load();
+        self.read_only = loaded.is_err();
+        let mut config = loaded?.unwrap_or_default();
+        config.add_roots(roots);
+
+        self.config = config;
+        self.scan_report = ScanReport::default();
+        self.reset_index();
+        self.flush()
+    }
+
+    /// A snapshot of the library to scan on another thread.
+    pub fn background_scan(&self) -> BackgroundScan {
+        BackgroundScan {
+            config: self.config.clone(),
+            roots: None,
+        }
+    }
+
+    /// Merges the result of a [`BackgroundScan`] into the library. Only the
+    /// entries of the scanned roots are replaced, user data that changed while
+    /// the scan ran, like favorites or play history, is kept. Roots that were
+    /// removed or got other discovery rules in the meantime are left as they are.
+    pub fn finish_scan(&mut self, result: ScanResult) -> Result<()> {
+        self.check_writable()?;
+        let ScanResult { config, roots, report } = result;
+
+        let mut merged = vec![];
+        for scanned in config.roots.into_iter().filter(|r| roots.contains(&r.path)) {
+            if let Some(root) = self.config.roots.iter_mut().find(|r| r.path == scanned.path)
+                && root.discovery == scanned.discovery
+            {
+                root.dirs = scanned.dirs;
+                root.last_scan_time = scanned.last_scan_time;
+                merged.push(scanned.path);
+            }
+        }
+
+        // found with other extensions, look for them again on the next scan
+        let stale_videos = config.video_extensions != self.config.video_extensions;
+        let mut fresh: Vec<Option<MovieData>> = config
+            .movies
+            .into_iter()
+            .filter(|item| merged.contains(&item.root))
+            .map(|mut item| {
+                if stale_videos {
+                    item.videos.clear();
+                }
+                Some(item)
+            })
+            .collect();
+        let fresh_ids: AHashMap<MovieId, usize> = fresh
+            .iter()
+            .flatten()
+            .enumerate()
+            .map(|(i, item)| (item.id.clone(), i))
+            .collect();
+
+        // entries keep their place, the ones the scan found are added at the end
+        let mut movies = Vec::with_capacity(self.config.movies.len());
+        for old in std::mem::take(&mut self.config.movies) {
+            if !merged.contains(&old.root) {
+                movies.push(old);
+            } else if let Some(mut item) = fresh_ids.get(&old.id).and_then(|&i| fresh[i].take()) {
+                item.inherit_user_data(old);
+                movies.push(item);
+            }
+        }
+        movies.extend(fresh.into_iter().flatten());
+        self.config.movies = movies;
+
+        self.config.shows.retain(|show| !merged.contains(&show.root));
+        self.config
+            .shows
+            .extend(config.shows.into_iter().filter(|show| merged.contains(&show.root)));
+
+        self.scan_report = report;
+        self.reset_index();
+        self.flush()
+    }
+
+    /// Scans only the roots that contain one of `paths`, e.g. the paths reported
+    /// by a [`LibraryWatcher`](crate::watch::LibraryWatcher). Returns whether any entry was added, updated or removed.
+    ///
+    /// The scan runs on the calling thread, see [`BackgroundScan::changed`] to
+    /// run it on a worker instead.
+    pub fn apply_changes(&mut self, paths: &[PathBuf]) -> Result<bool> {
+        let roots = self.config.changed_roots(paths);
+        if roots.is_empty() {
+            return Ok(false);
+        }
+
+        self.scan_report = Self::scan(&mut self.config, |root| roots.iter().any(|r| r == root), &|_| {});
+        let changed = self.scan_report.has_changes();
+        if changed {
+            self.reset_index();
+        }
+
+        self.flush()?;
+        Ok(changed)
+    }
+
+    /// Brings the entries of the roots selected by `only` up to date with the
+    /// file system, entries of other roots are left as they are.
+    ///
+    /// Directories are walked one after another in inode order, the nfo files
+    /// found are parsed in parallel.
+    fn scan(
+        config: &mut Config,
+        only: impl Fn(&Path) -> bool,
+        progress: &(dyn Fn(ScanProgress) + Sync),
+    ) -> ScanReport {
+        let mut report = ScanReport::default();
+        let tracker = ScanTracker::new(progress);
+        let is_scanned = |config: &Config, root: &Path| only(root) && config.is_root_available(root);
+        let scanned_roots: Vec<PathBuf> = config
+            .roots
+            .iter()
+            .filter(|r| is_scanned(config, &r.path))
+            .map(|r| r.path.clone())
+            .collect();
+
+        let mut shows = Vec::with_capacity(config.shows.len());
+        let mut stale_shows = vec![];
+        for show in std::mem::take(&mut config.shows) {
+            if !is_scanned(config, &show.root) {
+                shows.push(show);
+                continue;
+            }
+
+            let Ok(metadata) = std::fs::metadata(&show.path) else {
+                info!("{:?} no longer exists, removed from library", show.path);
+                continue;
+            };
+
+            if metadata.modified().ok() != show.nfo_mtime {
+                stale_shows.push(shows.len());
+            }
+            shows.push(show);
+        }
+
+        tracker.found(stale_shows.len());
+        let fresh_shows: Vec<_> = stale_shows
+            .par_iter()
+            .map(|&i| tracker.parsed(ShowData::load(&shows[i].path, &shows[i].root)))
+            .collect();
+
+        for (i, fresh) in stale_shows.into_iter().zip(fresh_shows) {
+            match fresh {
+                Ok(fresh) => {
+                    info!("{:?} changed, metadata updated", fresh.path);
+                    shows[i] = fresh;
+                }
+                Err(e) => report.fail(shows[i].path.clone(), e),
+            }
+        }
+        config.shows = shows;
+
+        let mut missing = vec![];
+        let mut movies = Vec::with_capacity(config.movies.len());
+        let mut stale_movies = vec![];
+        for mut item in std::mem::take(&mut config.movies) {
+            if item.root.as_os_str().is_empty()
+                && let Some(root) = config.roots.iter().find(|r| item.path.starts_with(&r.path))
+            {
+                item.root = root.path.clone();
+            }
+
+            if !is_scanned(config, &item.root) {
+                movies.push(item);
+                continue;
+            }
+
+            let Ok(metadata) = std::fs::metadata(&item.path) else {
+                missing.push(item);
+                continue;
+            };
+
+            // the show is gone, scan it again in case it's a movie now
+            if let Some(show) = &item.show
+                && config.shows.iter().all(|s| &s.path != show)
+            {
+                missing.push(item);
+                continue;
+            }
+
+            let mtime = metadata.modified().ok();
+            if mtime != item.nfo_mtime {
+                stale_movies.push((movies.len(), mtime));
+            }
+            movies.push(item);
+        }
+
+        tracker.found(stale_movies.len());
+        let fresh_movies: Vec<_> = stale_movies
+            .par_iter()
+            .map(|&(i, _)| tracker.parsed(Movie::load(&movies[i].path)))
+            .collect();
+
+        // user owned fields are kept, on failure the old metadata stays
+        for ((i, mtime), fresh) in stale_movies.into_iter().zip(fresh_movies) {
+            let item = &mut movies[i];
+            match fresh {
+                Ok(movie) => {
+                    info!("{:?} changed, metadata updated", item.path);
+                    item.movie = movie;
+                    item.videos.clear();
+                    report.updated += 1;
+                }
+                Err(e) => {
+                    warn!("{:?} nfo parse failed: {e}", item.path);
+                    report.fail(item.path.clone(), e);
+                }
+            }
+            item.nfo_mtime = mtime;
+        }
+        config.movies = movies;
+
+        let known_files: AHashSet<PathBuf> = config
+            .movies
+            .iter()
+            .map(|item| item.path.clone())
+            .chain(config.shows.iter().map(|show| show.path.clone()))
+            .collect();
+
+        // ids of missing entries stay reserved, they are reused if the entry just moved
+        let mut ids: AHashSet<MovieId> = config
+            .movies
+            .iter()
+            .chain(&missing)
+            .map(|item| item.id.clone())
+            .collect();
+
+        let now = SystemTime::now();
+        let mut new_nfos = vec![];
+        // known files of the walked roots the discovery rules still pick up
+        let mut walked = vec![];
+        let mut kept = AHashSet::new();
+        // inodes of the directories before and after the walk, to tell a
+        // renamed folder by
+        let mut old_inodes = AHashMap::new();
+        let mut new_inodes = AHashMap::new();
+        // directories whose entries changed may have gained or lost a video
+        let mut changed_dirs = AHashSet::new();
+        for root in config.roots.iter_mut().filter(|r| r.enabled && only(&r.path)) {
+            let before: AHashMap<PathBuf, (Option<SystemTime>, u64)> = root
+                .dirs
+                .iter()
+                .map(|(rel, state)| (rel.clone(), (state.mtime, state.inode)))
+                .collect();
+
+            let found = find_new_nfo_with_progress(
+                &root.path,
+                &root.discovery,
+                &mut root.dirs,
+                &known_files,
+                &mut |dirs, found| tracker.walked(dirs, found),
+            );
+
+            match found {
+                Ok(walk) => {
+                    new_nfos.extend(walk.found.into_iter().map(|nfo| (root.path.clone(), nfo)));
+                    kept.extend(walk.kept);
+                    walked.push(root.path.clone());
+                    old_inodes.extend(before.iter().map(|(rel, (_, inode))| (root.path.join(rel), *inode)));
+                    new_inodes.extend(root.dirs.iter().map(|(rel, state)| (root.path.join(rel), state.inode)));
+                }
+                Err(e) => {
+                    warn!("{:?} scan failed: {e}", root.path);
+                    continue;
+                }
+            }
+            root.last_scan_time = now;
+
+            changed_dirs.extend(
+                root.dirs
+                    .iter()
+                    .filter(|(rel, state)| before.get(*rel) != Some(&(state.mtime, state.inode)))
+                    .map(|(rel, _)| root.path.join(rel)),
+            );
+        }
+
+        // e.g. a new `.kkignore` or exclude glob
+        let is_dropped = |root: &Path, path: &Path| walked.iter().any(|r| r == root) && !kept.contains(path);
+        config.shows.retain(|show| {
+            let dropped = is_dropped(&show.root, &show.path);
+            if dropped {
+                info!("{:?} no longer picked up, removed from library", show.path);
+            }
+            !dropped
+        });
+
+        let count = config.movies.len();
+        config.movies.retain(|item| {
+            let dropped = is_dropped(&item.root, &item.path);
+            if dropped {
+                info!("{:?} no longer picked up, removed from library", item.path);
+            }
+            !dropped
+        });
+        report.removed += count - config.movies.len();
+
+        let (show_nfos, new_nfos): (Vec<_>, Vec<_>) =
+            new_nfos.into_iter().partition(|(_, nfo)| nfo.kind == NfoKind::Show);
+
+        let new_shows: Vec<_> = show_nfos
+            .into_par_iter()
+            .map(|(root, nfo)| (tracker.parsed(ShowData::load(&nfo.path, &root)), nfo.path))
+            .collect();
+
+        for (show, path) in new_shows {
+            match show {
+                Ok(show) => config.shows.push(show),
+                Err(e) => report.fail(path, e),
+            }
+        }
+
+        let extensions = &config.video_extensions;
+        let new_movies: Vec<_> = new_nfos
+            .into_par_iter()
+            .map(|(root, nfo)| {
+                let item = tracker.parsed(Self::load_movie_from_nfo(&nfo.path, now));
+                // only needed to recognize moved entries by their videos
+                let videos = match item.is_ok() && !missing.is_empty() {
+                    true => find_videos(&nfo.path, extensions).unwrap_or_default(),
+                    false => vec![],
+                };
+                (item, videos, root, nfo)
+            })
+            .collect();
+
+        let old_names = count_file_names(missing.iter().flat_map(|item| &item.videos));
+        let new_names = count_file_names(new_movies.iter().flat_map(|(_, videos, _, _)| videos));
+        let is_unique = |name: &OsStr| old_names.get(name) == Some(&1) && new_names.get(name) == Some(&1);
+
+        for (item, videos, root, nfo) in new_movies {
+            let mut item = match item {
+                Ok(item) => item,
+                Err(e) => {
+                    report.fail(nfo.path, e);
+                    continue;
+                }
+            };
+
+            if let NfoKind::Episode(show) = nfo.kind {
+                item.show = Some(show);
+            }
+
+            item.root = root;
+            let moved = MovieId::from_nfo(&item.movie)
+                .and_then(|id| missing.iter().position(|old| old.id == id))
+                .or_else(|| {
+                    let dir = item.path.parent()?;
+                    let inode = new_inodes.get(dir).filter(|&&inode| inode != 0);
+                    missing.iter().position(|old| {
+                        let old_dir = old.path.parent();
+                        let renamed_dir = inode.is_some()
+                            && old_dir != Some(dir)
+                            && old_dir.and_then(|old_dir| old_inodes.get(old_dir)) == inode;
+                        old.is_same_movie(&item, renamed_dir, &videos, is_unique)
+                    })
+                });
+
+            if let Some(i) = moved {
+                let old = missing.swap_remove(i);
+                info!("{:?} moved to {:?}", old.path, item.path);
+                item.inherit_user_data(old);
+                report.updated += 1;
+            } else {
+                item.id = MovieId::claim(Some(item.id), &mut ids);
+                report.added += 1;
+            }
+
+            config.movies.push(item);
+        }
+
+        for item in &missing {
+            info!("{:?} no longer exists, removed from library", item.path);
+        }
+        report.removed += missing.len();
+
+        Self::resolve_videos(config, &scanned_roots, &changed_dirs);
+        report
+    }
+
+    /// Looks for the videos of new and changed entries, of entries whose
+    /// videos are missing or gone and of entries in `changed_dirs`, e.g. a
+    /// `-cd2` copied next to `-cd1`.
+    fn resolve_videos(config: &mut Config, roots: &[PathBuf], changed_dirs: &AHashSet<PathBuf>) {
+        let extensions = &config.video_extensions;
+        config
+            .movies
+            .par_iter_mut()
+            .filter(|item| roots.contains(&item.root))
+            .filter(|item| {
+                item.videos.is_empty()
+                    || item.videos.iter().any(|v| !v.is_file())
+                    || item.path.parent().is_some_and(|dir| changed_dirs.contains(dir))
+            })
+            .for_each(|item| {
+                item.videos = find_videos(&item.path, extensions).unwrap_or_else(|e| {
+                    warn!("{:?} video lookup failed: {e}", item.path);
+                    vec![]
+                });
+            });
+    }
+
+    pub fn load_movie_from_nfo(path: &Path, added_time: SystemTime) -> Result<MovieData> {
+        let nfo_mtime = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        let movie = Movie::load(path).inspect_err(|e| warn!("{path:?} nfo parse failed: {e}"))?;
+
+        Ok(MovieData {
+            id: MovieId::from_nfo(&movie).unwrap_or_else(MovieId::random),
+            path: path.to_owned(),
+            movie,
+            root: PathBuf::new(),
+            show: None,
+            nfo_mtime,
+            videos: vec![],
+            added_time,
+            fav: false,
+            markers: vec![],
+            resume_pos: None,
+            last_played: None,
+            play_count: 0,
+            watched: false,
+        })
+    }
+
+    /// Reads every directory and re-parses every nfo again, for changes the
+    /// incremental scan can't see, e.g. a file restored with its old mtime.
+    pub fn full_rescan(&mut self) -> Result<()> {
+        self.config.forget_scan_state();
+        self.flush()?;
+        self.reload()
+    }
+
+    /// Scans the known library roots for changes.
+    pub fn reload(&mut self) -> Result<()> {
+        let loaded = self.load(&[]);
+        self.read_only = loaded.is_err();
+        (self.config, self.scan_report) = loaded?;
+        self.reset_index();
+        self.flush()
+    }
+
+    /// Rebuilds the visible index, entries of disabled roots are hidden and
+    /// episodes are only listed through their show.
+    fn reset_index(&mut self) {
+        self.positions = self
+            .config
+            .movies
+            .iter()
+            .enumerate()
+            .map(|(i, d)| (d.id.clone(), i as u32))
+            .collect();
+
+        self.index_ref = self
+            .config
+            .movies
+            .iter()
+            .enumerate()
+            .filter(|(_, d)| !d.is_episode() && self.config.is_root_enabled(&d.root))
+            .map(|(i, _)| i as u32)
+            .collect();
+
+        self.order_by_fav_index.dirty = true;
+        self.order_by_random_index.dirty = true;
+        self.order_by_last_played_index.dirty = true;
+        self.filter_by_unwatched_index.dirty = true;
+        self.search_result_index.dirty = true;
+        self.search_index = None;
+        self.filter_index.clear();
+        self.facets.clear();
+        self.sort_index.clear();
+    }
+
+    pub fn roots(&self) -> &[LibraryRoot] {
+        &self.config.roots
+    }
+
+    /// Files that failed to parse during the last load or reload.
+    pub fn scan_report(&self) -> &ScanReport {
+        &self.scan_report
+    }
+
+    /// Registers a new library root and scans it.
+    pub fn add_root(&mut self, path: PathBuf) -> Result<()> {
+        if self.config.root(&path).is_some() {
+            return Ok(());
+        }
+
+        self.config.roots.push(LibraryRoot::new(path));
+        self.flush()?;
+        self.reload()
+    }
+
+    /// Makes `roots` the enabled library roots, e.g. the ones currently
+    /// configured by the user. Unknown roots are registered for the next scan
+    /// and every other root is disabled, its entries are kept in case it
+    /// comes back.
+    pub fn sync_roots(&mut self, roots: &[PathBuf]) -> Result<()> {
+        let mut changed = false;
+        for root in &mut self.config.roots {
+            let enabled = roots.contains(&root.path);
+            changed |= root.enabled != enabled;
+            root.enabled = enabled;
+        }
+
+        let len = self.config.roots.len();
+        self.config.add_roots(roots);
+        if !changed && self.config.roots.len() == len {
+            return Ok(());
+        }
+
+        self.reset_index();
+        self.flush()
+    }
+
+    /// Forgets a library root together with all of its entries.
+    pub fn remove_root(&mut self, path: &Path) -> Result<()> {
+        let len = self.config.roots.len();
+        self.config.roots.retain(|r| r.path != path);
+        if self.config.roots.len() == len {
+            return Ok(());
+        }
+
+        self.config.movies.retain(|d| d.root != path);
+        self.config.shows.retain(|s| s.root != path);
+        self.reset_index();
+        self.flush()
+    }
+
+    /// Disabled roots are neither scanned nor listed, but their entries are kept.
+    pub fn set_root_enabled(&mut self, path: &Path, enabled: bool) -> Result<()> {
+        let Some(root) = self.config.roots.iter_mut().find(|r| r.path == path) else {
+            return Ok(());
+        };
+
+        if root.enabled != enabled {
+            root.enabled = enabled;
+            self.reset_index();
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Changes how a root finds its nfo files. Entries the new rules would no
+    /// longer pick up are removed and the whole root is scanned again.
+    pub fn set_root_discovery(&mut self, path: &Path, discovery: Discovery) -> Result<()> {
+        let Some(root) = self.config.roots.iter_mut().find(|r| r.path == path) else {
+            return Ok(());
+        };
+
+        if root.discovery == discovery {
+            return Ok(());
+        }
+
+        let found: AHashSet<PathBuf> =
+            find_new_nfo(path, &discovery, &mut DirStates::new(), &AHashSet::new())?
+                .into_iter()
+                .map(|nfo| nfo.path)
+                .collect();
+
+        root.discovery = discovery;
+        root.dirs.clear();
+        self.config.movies.retain(|d| d.root != path || found.contains(&d.path));
+        self.config.shows.retain(|s| s.root != path || found.contains(&s.path));
+        self.flush()?;
+        self.reload()
+    }
+
+    pub fn video_extensions(&self) -> &[String] {
+        &self.config.video_extensions
+    }
+
+    /// Changes which files count as videos and looks for the videos of every
+    /// entry again.
+    pub fn set_video_extensions(&mut self, extensions: Vec<String>) -> Result<()> {
+        if self.config.video_extensions == extensions {
+            return Ok(());
+        }
+
+        self.config.video_extensions = extensions;
+        for item in &mut self.config.movies {
+            item.videos.clear();
+        }
+        self.flush()?;
+        self.reload()
+    }
+
+    pub fn flush(&self) -> Result<()> {
+        self.check_writable()?;
+        self.storage.flush(&self.config)
+    }
+
+    /// Persists a single changed entry.
+    fn update(&self, id: &MovieId) -> Result<()> {
+        self.check_writable()?;
+        self.storage.update(&self.config, id)
+    }
+
+    /// Whether the last load failed. Nothing is written then, so the stored
+    /// library stays as it is until it loads again.
+    #[inline]
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            bail!("the stored library could not be loaded, not overwriting it");
+        }
+        Ok(())
+    }
+
+    /// Position of the entry in `Config.movies`, only valid until the next reset.
+    #[inline]
+    fn position(&self, id: &MovieId) -> Option<usize> {
+        self.positions.get(id).map(|i| *i as usize)
+    }
+
+    fn movie_mut(&mut self, id: &MovieId) -> Option<&mut MovieData> {
+        let i = self.position(id)?;
+        self.config.movies.get_mut(i)
+    }
+
+    /// Drops positions that are hidden, e.g. entries of a disabled root.
+    fn retain_visible(&self, data: &mut Vec<u32>) {
+        if self.index_ref.len() == self.config.movies.len() {
+            return;
+        }
+
+        let visible: AHashSet<u32> = self.index_ref.iter().copied().collect();
+        data.retain(|i| visible.contains(i));
+    }
+
+    /// Runs `query` against the storage backend, results are in library order.
+    pub fn query<'a>(&'a mut self, query: &StorageQuery) -> Result<DatabaseSlice<'a>> {
+        let mut data = self.storage.query(&self.config, query)?;
+        self.retain_visible(&mut data);
+
+        let index = self.query_index.idx.insert(data);
+        Ok(DatabaseSlice::new(&self.config.movies, index))
+    }
+
+    /// Case and accent insensitive search over number, title, actors, tags,
+    /// genres and outline, best match first.
+    pub fn search<'a>(&'a mut self, query: &str) -> DatabaseSlice<'a> {
+        if !self.search_result_index.dirty
+            && self.search_query == query
+            && let Some(ref idx) = self.search_result_index.idx
+        {
+            return DatabaseSlice::new(&self.config.movies, idx);
+        }
+
+        self.search_result_index.dirty = false;
+        self.search_query = query.to_string();
+
+        let movies = &self.config.movies;
+        let search_index = self.search_index.get_or_insert_with(|| SearchIndex::new(movies));
+        let mut data = search_index.search(query);
+        self.retain_visible(&mut data);
+
+        let index = self.search_result_index.idx.insert(data);
+        DatabaseSlice::new(&self.config.movies, index)
+    }
+
+    /// Newest first. Results are cached per filter, and a narrowed filter
+    /// (`parent.and(f)`) only scans the cached result of its parent.
+    pub fn filter<'a>(&'a mut self, filter: &Filter) -> DatabaseSlice<'a> {
+        self.ensure_filter_index(filter);
+        let index = self.filter_index[filter].idx.as_deref().unwrap_or_default();
+        DatabaseSlice::new(&self.config.movies, index)
+    }
+
+    /// Facet counts of the movies matching `filter`.
+    pub fn facets(&mut self, filter: &Filter) -> &Facets {
+        if !self.facets.contains_key(filter) {
+            self.ensure_filter_index(filter);
+            let movies = &self.config.movies;
+            let idx = self.filter_index[filter].idx.as_deref().unwrap_or_default();
+            let facets = Facets::new(idx.iter().filter_map(|i| movies.get(*i as usize)));
+
+            if self.facets.len() >= FILTER_CACHE_LIMIT {
+                self.facets.clear();
+            }
+            self.facets.insert(filter.clone(), facets);
+        }
+
+        &self.facets[filter]
+    }
+
+    fn ensure_filter_index(&mut self, filter: &Filter) {
+        if let Some(table) = self.filter_index.get(filter) && !table.dirty && table.idx.is_some() {
+            return;
+        }
+
+        let candidates: Vec<u32> = match filter.parent() {
+            Some(parent) => {
+                self.ensure_filter_index(&parent);
+                self.filter_index[&parent].idx.clone().unwrap_or_default()
+            }
+            // newest first, positions don't follow the added time since entries can move
+            None => SortKey::AddedTime.sort(&self.config.movies, &self.index_ref, SortOrder::Descending),
+        };
+
+        let data: Vec<u32> = candidates
+            .into_iter()
+            .filter(|i| {
+                self.config.movies.get(*i as usize)
+                    .map(|d| filter.matches(d))
+                    .unwrap_or(false)
+            })
+            .collect();
+
+        if self.filter_index.len() >= FILTER_CACHE_LIMIT {
+            self.filter_index.clear();
+        }
+
+        self.filter_index.insert(filter.clone(), IndexCacheTable {
+            idx: Some(data),
+            dirty: false,
+        });
+    }
+
+    pub fn filter_by_fav<'a>(&'a mut self) -> DatabaseSlice<'a> {
+        if !self.order_by_fav_index.dirty && let Some(ref idx) = self.order_by_fav_index.idx {
+            return DatabaseSlice::new(&self.config.movies, idx);
+        }
+
+        self.order_by_fav_index.dirty = false;
+        let mut data = match self.storage.query(&self.config, &StorageQuery::Fav) {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("fav query failed: {e}");
+                vec![]
+            }
+        };
+        self.retain_visible(&mut data);
+
+        let index = self.order_by_fav_index.idx.insert(data);
+        DatabaseSlice::new(&self.config.movies, index)
+    }
+
+    pub fn order_by_random<'a>(&'a mut self) -> DatabaseSlice<'a> {
+        if !self.order_by_random_index.dirty && let Some(ref idx) = self.order_by_random_index.idx {
+            return DatabaseSlice::new(&self.config.movies, idx);
+        }
+
+        self.order_by_random_index.dirty = false;
+        let mut data = self.index_ref.clone();
+
+        let mut rng = rng();
+        data.shuffle(&mut rng);
+
+        let index = self.order_by_random_index.idx.insert(data);
+        DatabaseSlice::new(&self.config.movies, index)
+    }
+
+    /// Newest first.
+    pub fn order_by_added_time<'a>(&'a mut self) -> DatabaseSlice<'a> {
+        self.sort(SortKey::AddedTime, SortOrder::Descending)
+    }
+
+    /// The whole library ordered by `key`, see [`SortKey`] for how each key compares.
+    pub fn sort<'a>(&'a mut self, key: SortKey, order: SortOrder) -> DatabaseSlice<'a> {
+        let table = self.sort_index.entry((key, order)).or_default();
+        if table.dirty || table.idx.is_none() {
+            table.dirty = false;
+            table.idx = Some(key.sort(&self.config.movies, &self.index_ref, order));
+        }
+
+        DatabaseSlice::new(&self.config.movies, table.idx.as_deref().unwrap_or_default())
+    }
+
+    /// Favorites ordered by `key`.
+    pub fn sort_fav<'a>(&'a mut self, key: SortKey, order: SortOrder) -> DatabaseSlice<'a> {
+        self.filter_by_fav();
+        let fav = self.order_by_fav_index.idx.as_deref().unwrap_or_default();
+
+        let index = self.sorted_subset_index.idx.insert(key.sort(&self.config.movies, fav, order));
+        DatabaseSlice::new(&self.config.movies, index)
+    }
+
+    /// Movies not watched yet ordered by `key`.
+    pub fn sort_unwatched<'a>(&'a mut self, key: SortKey, order: SortOrder) -> DatabaseSlice<'a> {
+        self.filter_by_unwatched();
+        let unwatched = self.filter_by_unwatched_index.idx.as_deref().unwrap_or_default();
+
+        let index = self.sorted_subset_index.idx.insert(key.sort(&self.config.movies, unwatched, order));
+        DatabaseSlice::new(&self.config.movies, index)
+    }
+
+    /// Most recently played movies first, movies never played are left out.
+    pub fn order_by_last_played<'a>(&'a mut self) -> DatabaseSlice<'a> {
+        if !self.order_by_last_played_index.dirty && let Some(ref idx) = self.order_by_last_played_index.idx {
+            return DatabaseSlice::new(&self.config.movies, idx);
+        }
+
+        self.order_by_last_played_index.dirty = false;
+        let movies = &self.config.movies;
+        let mut data: Vec<u32> = self
+            .index_ref
+            .iter()
+            .copied()
+            .filter(|i| {
+                movies.get(*i as usize)
+                    .map(|d| d.last_played.is_some())
+                    .unwrap_or(false)
+            })
+            .collect();
+        data.sort_by_key(|i| std::cmp::Reverse(movies[*i as usize].last_played));
+
+        let index = self.order_by_last_played_index.idx.insert(data);
+        DatabaseSlice::new(&self.config.movies, index)
+    }
+
+    pub fn filter_by_unwatched<'a>(&'a mut self) -> DatabaseSlice<'a> {
+        if !self.filter_by_unwatched_index.dirty && let Some(ref idx) = self.filter_by_unwatched_index.idx {
+            return DatabaseSlice::new(&self.config.movies, idx);
+        }
+
+        self.filter_by_unwatched_index.dirty = false;
+        let data: Vec<u32> = self
+            .index_ref
+            .iter()
+            .copied()
+            .filter(|i| {
+                self.config.movies.get(*i as usize)
+                    .map(|d| !d.watched)
+                    .unwrap_or(false)
+            })
+            .collect();
+        // newest first, positions don't follow the added time since entries can move
+        let data = SortKey::AddedTime.sort(&self.config.movies, &data, SortOrder::Descending);
+
+        let index = self.filter_by_unwatched_index.idx.insert(data);
+        DatabaseSlice::new(&self.config.movies, index)
+    }
+
+    /// Shows of enabled roots ordered by title. A show is addressed by the
+    /// path of its `tvshow.nfo`, like its episodes do in [`MovieData::show`].
+    pub fn shows(&self) -> Vec<&ShowData> {
+        let title = |s: &ShowData| {
+            s.show.sorttitle.as_deref()
+                .filter(|t| !t.trim().is_empty())
+                .unwrap_or(&s.show.title)
+                .to_owned()
+        };
+
+        let mut shows: Vec<&ShowData> = self
+            .config
+            .shows
+            .iter()
+            .filter(|s| self.config.is_root_enabled(&s.root))
+            .collect();
+        shows.sort_by(|a, b| natural_cmp(&title(a), &title(b)));
+        shows
+    }
+
+    /// The show of the `tvshow.nfo` at `path`.
+    pub fn get_show(&self, path: &Path) -> Option<&ShowData> {
+        self.config.shows.iter().find(|s| s.path == path)
+    }
+
+    /// Episodes of a show ordered by season and episode number.
+    fn show_episodes(&self, show: &Path) -> Vec<(u32, (u32, u32))> {
+        let mut episodes: Vec<(u32, (u32, u32))> = self
+            .config
+            .movies
+            .iter()
+            .enumerate()
+            .filter(|(_, d)| d.show.as_deref() == Some(show))
+            .map(|(i, d)| (i as u32, d.episode_number().unwrap_or((1, 0))))
+            .collect();
+        episodes.sort_by(|(a, a_num), (b, b_num)| {
+            let movies = &self.config.movies;
+            a_num.cmp(b_num).then_with(|| movies[*a as usize].path.cmp(&movies[*b as usize].path))
+        });
+        episodes
+    }
+
+    /// Season numbers of a show, specials (season 0) come last.
+    pub fn seasons(&self, show: &Path) -> Vec<u32> {
+        let mut seasons: Vec<u32> = self
+            .show_episodes(show)
+            .into_iter()
+            .map(|(_, (season, _))| season)
+            .collect();
+        seasons.dedup();
+        seasons.sort_by_key(|season| (*season == 0, *season));
+        seasons
+    }
+
+    /// Episodes of a show, or of one of its seasons.
+    pub fn episodes<'a>(&'a mut self, show: &Path, season: Option<u32>) -> DatabaseSlice<'a> {
+        let data = self
+            .show_episodes(show)
+            .into_iter()
+            .filter(|(_, (s, _))| season.is_none_or(|season| season == *s))
+            .map(|(i, _)| i)
+            .collect();
+
+        let index = self.episode_index.idx.insert(data);
+        DatabaseSlice::new(&self.config.movies, index)
+    }
+
+    /// The episode after `id` in the same show, the next season continues where
+    /// the last one ended. Specials only lead to other specials.
+    pub fn next_episode(&self, id: &MovieId) -> Option<MovieId> {
+        let i = self.position(id)?;
+        let show = self.config.movies.get(i)?.show.as_deref()?;
+
+        let episodes = self.show_episodes(show);
+        let pos = episodes.iter().position(|(e, _)| *e as usize == i)?;
+        let (_, (season, _)) = episodes[pos];
+        episodes[pos + 1..]
+            .iter()
+            .find(|(_, (s, _))| (*s == 0) == (season == 0))
+            .map(|(e, _)| self.config.movies[*e as usize].id.clone())
+    }
+
+    /// Updates the watch history when a movie starts playing.
+    pub fn record_play(&mut self, id: &MovieId) -> Result<()> {
+        let Some(data) = self.movie_mut(id) else {
+            return Ok(());
+        };
+
+        data.last_played = Some(SystemTime::now());
+        data.play_count += 1;
+        self.order_by_last_played_index.dirty = true;
+        for ((key, _), table) in self.sort_index.iter_mut() {
+            if *key == SortKey::LastPlayed {
+                table.dirty = true;
+            }
+        }
+        self.update(id)
+    }
+
+    pub fn set_fav(&mut self, id: &MovieId, fav: bool) -> Result<()> {
+        let Some(data) = self.movie_mut(id) else {
+            return Ok(());
+        };
+
+        if data.fav != fav {
+            data.fav = fav;
+            self.order_by_fav_index.dirty = true;
+            self.update(id)?;
+        }
+
+        Ok(())
+    }
+
+    /// Flips the favorite flag and returns the new value.
+    pub fn toggle_fav(&mut self, id: &MovieId) -> Result<Option<bool>> {
+        let Some(data) = self.get_movie(id) else {
+            return Ok(None);
+        };
+
+        let fav = !data.fav;
+        self.set_fav(id, fav)?;
+        Ok(Some(fav))
+    }
+
+    pub fn set_watched(&mut self, id: &MovieId, watched: bool) -> Result<()> {
+        let Some(data) = self.movie_mut(id) else {
+            return Ok(());
+        };
+
+        if data.watched != watched {
+            data.watched = watched;
+            self.filter_by_unwatched_index.dirty = true;
+            self.update(id)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn get_movie(&self, id: &MovieId) -> Option<&MovieData> {
+        self.config.movies.get(self.position(id)?)
+    }
+
+    pub fn resume_pos(&self, id: &MovieId) -> Option<f64> {
+        self.get_movie(id).and_then(|d| d.resume_pos)
+    }
+
+    /// Remembers where playback stopped so the movie can be resumed later.
+    /// Positions near the start or the end of the movie clear the resume point,
+    /// and playing most of the duration marks the movie as watched.
+    pub fn save_progress(&mut self, id: &MovieId, pos: f64, duration: f64) -> Result<()> {
+        let resume_pos = (pos >= RESUME_MIN_POS && pos < duration - RESUME_END_MARGIN).then_some(pos);
+        self.set_resume_pos(id, resume_pos)?;
+
+        if duration > 0. && pos >= duration * WATCHED_RATIO {
+            self.set_watched(id, true)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn set_resume_pos(&mut self, id: &MovieId, pos: Option<f64>) -> Result<()> {
+        let Some(data) = self.movie_mut(id) else {
+            return Ok(());
+        };
+
+        if data.resume_pos != pos {
+            data.resume_pos = pos;
+            self.update(id)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn markers(&self, id: &MovieId) -> &[f64] {
+        self.get_movie(id)
+            .map(|d| d.markers.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Stores a marker for the movie and returns its updated marker list.
+    /// Markers closer than `MARKER_TOLERANCE` to an existing one are ignored.
+    pub fn add_marker(&mut self, id: &MovieId, time: f64) -> Result<Option<&[f64]>> {
+        let Some(data) = self.movie_mut(id) else {
+            return Ok(None);
+        };
+
+        if !data.markers.iter().any(|m| (m - time).abs() < MARKER_TOLERANCE) {
+            let pos = data.markers.partition_point(|m| *m < time);
+            data.markers.insert(pos, time);
+            self.update(id)?;
+        }
+
+        Ok(Some(self.markers(id)))
+    }
+
+    /// Removes every marker within `MARKER_TOLERANCE` of `time`.
+    pub fn remove_marker(&mut self, id: &MovieId, time: f64) -> Result<bool> {
+        let Some(data) = self.movie_mut(id) else {
+            return Ok(false);
+        };
+
+        let len = data.markers.len();
+        data.markers.retain(|m| (m - time).abs() >= MARKER_TOLERANCE);
+        let removed = data.markers.len() != len;
+        if removed {
+            self.update(id)?;
+        }
+
+        Ok(removed)
+    }
+}
+
+#[derive(Clone)]
+pub struct IndexedMovieData<'a> {
+    pub movie: &'a MovieData,
+    pub id: &'a MovieId,
+}
+
+pub struct DatabaseSlice<'a> {
+    items: &'a [MovieData],
+    index: &'a [u32],
+    i: usize,
+}
+
+impl<'a> DatabaseSlice<'a> {
+    pub fn new(items: &'a [MovieData], index: &'a [u32]) -> Self {
+        Self { items, index, i: 0 }
+    }
+}
+
+impl<'a> Iterator for DatabaseSlice<'a> {
+    type Item = IndexedMovieData<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.i += 1;
+        self.index
+            .get(self.i - 1)
+            .and_then(|i| {
+                let movie = self.items.get(*i as usize)?;
+                Some(IndexedMovieData { movie, id: &movie.id })
+            })
+    }
+}
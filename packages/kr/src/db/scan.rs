@@ -0,0 +1,169 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::db::{Config, Database};
+
+/// What the last scan changed and what went wrong, kept so the ui can tell
+/// the user which nfo files need fixing instead of silently leaving them out.
+#[derive(Clone, Debug, Default)]
+pub struct ScanReport {
+    pub failures: Vec<ParseFailure>,
+    /// Entries that are new to the library.
+    pub added: usize,
+    /// Entries whose nfo changed or that were moved.
+    pub updated: usize,
+    pub removed: usize,
+}
+
+impl ScanReport {
+    /// Whether any nfo file failed to parse.
+    #[inline]
+    pub fn has_failures(&self) -> bool {
+        !self.failures.is_empty()
+    }
+
+    #[inline]
+    pub fn has_changes(&self) -> bool {
+        self.added + self.updated + self.removed > 0
+    }
+
+    pub(crate) fn fail(&mut self, path: PathBuf, error: impl ToString) {
+        self.failures.push(ParseFailure {
+            path,
+            error: error.to_string(),
+        });
+    }
+}
+
+/// An nfo file that could not be read or parsed.
+#[derive(Clone, Debug)]
+pub struct ParseFailure {
+    pub path: PathBuf,
+    pub error: String,
+}
+
+/// Counts of a running scan, reported after every directory and every parsed
+/// nfo. `found` also counts known files that changed and are parsed again.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ScanProgress {
+    pub dirs: usize,
+    pub found: usize,
+    pub parsed: usize,
+    pub failed: usize,
+}
+
+/// Shared counters of a scan, nfo files are parsed on several threads.
+pub(crate) struct ScanTracker<'a> {
+    progress: &'a (dyn Fn(ScanProgress) + Sync),
+    dirs: AtomicUsize,
+    found: AtomicUsize,
+    parsed: AtomicUsize,
+    failed: AtomicUsize,
+}
+
+impl<'a> ScanTracker<'a> {
+    pub fn new(progress: &'a (dyn Fn(ScanProgress) + Sync)) -> Self {
+        Self {
+            progress,
+            dirs: AtomicUsize::new(0),
+            found: AtomicUsize::new(0),
+            parsed: AtomicUsize::new(0),
+            failed: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn walked(&self, dirs: usize, found: usize) {
+        self.dirs.fetch_add(dirs, Ordering::Relaxed);
+        self.found.fetch_add(found, Ordering::Relaxed);
+        self.report();
+    }
+
+    pub fn found(&self, found: usize) {
+        if found > 0 {
+            self.walked(0, found);
+        }
+    }
+
+    /// Counts the result of parsing one nfo and passes it on.
+    pub fn parsed<T, E>(&self, result: Result<T, E>) -> Result<T, E> {
+        let counter = if result.is_ok() { &self.parsed } else { &self.failed };
+        counter.fetch_add(1, Ordering::Relaxed);
+        self.report();
+        result
+    }
+
+    fn report(&self) {
+        (self.progress)(ScanProgress {
+            dirs: self.dirs.load(Ordering::Relaxed),
+            found: self.found.load(Ordering::Relaxed),
+            parsed: self.parsed.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+        });
+    }
+}
+
+/// A snapshot of the library that is scanned without the database, e.g. on a
+/// worker thread while the cached library is already shown. The result goes
+/// back through [`Database::finish_scan`].
+#[derive(Debug)]
+pub struct BackgroundScan {
+    pub(crate) config: Config,
+    // every root when unset
+    pub(crate) roots: Option<Vec<PathBuf>>,
+}
+
+impl BackgroundScan {
+    /// Scans only the roots that contain one of `paths`, e.g. the paths
+    /// reported by a [`LibraryWatcher`](crate::watch::LibraryWatcher).
+    pub fn changed(mut self, paths: &[PathBuf]) -> Self {
+        self.roots = Some(self.config.changed_roots(paths));
+        self
+    }
+
+    /// Whether there is nothing to scan, none of the changed paths is in the library.
+    pub fn is_empty(&self) -> bool {
+        self.roots.as_ref().is_some_and(Vec::is_empty)
+    }
+
+    /// Reads every directory and re-parses every nfo instead of only the changes.
+    pub fn full(mut self) -> Self {
+        self.config.forget_scan_state();
+        self
+    }
+
+    pub fn run(mut self, progress: impl Fn(ScanProgress) + Sync) -> ScanResult {
+        let roots = self.roots.take();
+        let only = |root: &Path| roots.as_ref().is_none_or(|roots| roots.iter().any(|r| r == root));
+        let scanned = self
+            .config
+            .roots
+            .iter()
+            .filter(|r| only(&r.path) && self.config.is_root_available(&r.path))
+            .map(|r| r.path.clone())
+            .collect();
+
+        let report = Database::scan(&mut self.config, only, &progress);
+        ScanResult {
+            config: self.config,
+            roots: scanned,
+            report,
+        }
+    }
+}
+
+/// The scanned library of a [`BackgroundScan`].
+#[derive(Clone, Debug)]
+pub struct ScanResult {
+    pub(crate) config: Config,
+    // the roots whose entries the scan brought up to date
+    pub(crate) roots: Vec<PathBuf>,
+    pub(crate) report: ScanReport,
+}
+
+impl ScanResult {
+    pub fn report(&self) -> &ScanReport {
+        &self.report
+    }
+}
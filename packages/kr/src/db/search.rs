@@ -0,0 +1,195 @@
+use std::{cmp::Ordering, collections::BTreeMap};
+
+use ahash::AHashMap;
+use unicode_normalization::{UnicodeNormalization, char::is_combining_mark};
+
+use crate::db::MovieData;
+
+// field weights, a hit in the number or title counts more than one in the outline
+const NUM_WEIGHT: f32 = 5.;
+const TITLE_WEIGHT: f32 = 3.;
+const ACTOR_WEIGHT: f32 = 3.;
+const TAG_WEIGHT: f32 = 2.;
+const OUTLINE_WEIGHT: f32 = 1.;
+// a term that only starts with the query token scores less than an exact one
+const PREFIX_FACTOR: f32 = 0.5;
+
+/// Inverted index over the searchable text of every movie.
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+    terms: BTreeMap<String, AHashMap<u32, f32>>,
+}
+
+impl SearchIndex {
+    pub fn new(movies: &[MovieData]) -> Self {
+        let mut index = Self::default();
+        for (i, data) in movies.iter().enumerate() {
+            index.add(i as u32, data);
+        }
+        index
+    }
+
+    fn add(&mut self, i: u32, data: &MovieData) {
+        let movie = &data.movie;
+
+        if let Some(num) = &movie.num {
+            self.add_text(i, num, NUM_WEIGHT);
+            // let "abc123" find "ABC-123"
+            let compact: String = normalize(num).chars().filter(|c| c.is_alphanumeric()).collect();
+            self.add_term(i, compact, NUM_WEIGHT);
+        }
+
+        self.add_text(i, &movie.title, TITLE_WEIGHT);
+        if let Some(title) = &movie.originaltitle {
+            self.add_text(i, title, TITLE_WEIGHT);
+        }
+
+        for actor in &movie.actor {
+            self.add_text(i, &actor.name, ACTOR_WEIGHT);
+        }
+
+        for tag in movie.tag.iter().chain(movie.genre.iter()).flatten() {
+            self.add_text(i, tag, TAG_WEIGHT);
+        }
+
+        // kodi nfo files usually only have a plot
+        for outline in movie.outline.iter().chain(movie.plot.iter()) {
+            self.add_text(i, outline, OUTLINE_WEIGHT);
+        }
+    }
+
+    fn add_text(&mut self, i: u32, text: &str, weight: f32) {
+        for term in tokenize(text) {
+            self.add_term(i, term, weight);
+        }
+    }
+
+    fn add_term(&mut self, i: u32, term: String, weight: f32) {
+        if term.is_empty() {
+            return;
+        }
+
+        let score = self.terms.entry(term).or_default().entry(i).or_default();
+        *score = score.max(weight);
+    }
+
+    /// Positions of the movies matching every token of `query`, best match first.
+    pub fn search(&self, query: &str) -> Vec<u32> {
+        let tokens = tokenize(query);
+        if tokens.is_empty() {
+            return vec![];
+        }
+
+        let mut scores: Option<AHashMap<u32, f32>> = None;
+        for token in tokens {
+            let mut token_scores: AHashMap<u32, f32> = AHashMap::new();
+            for (term, postings) in self.terms.range(token.clone()..) {
+                if !term.starts_with(&token) {
+                    break;
+                }
+
+                let factor = if *term == token { 1. } else { PREFIX_FACTOR };
+                for (i, weight) in postings {
+                    let score = token_scores.entry(*i).or_default();
+                    *score = score.max(weight * factor);
+                }
+            }
+
+            scores = Some(match scores {
+                None => token_scores,
+                Some(scores) => scores
+                    .into_iter()
+                    .filter_map(|(i, score)| token_scores.get(&i).map(|s| (i, score + s)))
+                    .collect(),
+            });
+        }
+
+        let mut result: Vec<(u32, f32)> = scores.unwrap_or_default().into_iter().collect();
+        // newer entries first when the score is the same
+        result.sort_unstable_by(|a, b| {
+            b.1.partial_cmp(&a.1)
+                .unwrap_or(Ordering::Equal)
+                .then(b.0.cmp(&a.0))
+        });
+        result.into_iter().map(|(i, _)| i).collect()
+    }
+}
+
+/// Lowercases and strips accents, full width forms are folded as well.
+///
+/// CJK characters are kept as they are, decomposing them would split Hangul
+/// syllables into jamo. The kana voicing marks are no accents either, `ば`
+/// stays apart from `は`, also when written as half width `ﾊﾞ`.
+pub fn normalize(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for c in text.chars() {
+        if is_cjk(c) {
+            folded.push(c);
+            continue;
+        }
+
+        for c in c.nfkd().filter(|c| is_voicing_mark(*c) || !is_combining_mark(*c)) {
+            folded.extend(c.to_lowercase());
+        }
+    }
+
+    // joins a kana with a voicing mark that came from a separate character
+    folded.nfc().collect()
+}
+
+#[inline]
+fn is_voicing_mark(c: char) -> bool {
+    matches!(c, '\u{3099}' | '\u{309a}')
+}
+
+/// Splits text into words, runs of CJK characters are split into bigrams
+/// since they are not separated by spaces. The last character of a run is
+/// kept on its own, so every character is the start of some token.
+pub fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut word = String::new();
+    let mut cjk: Vec<char> = vec![];
+
+    let flush_cjk = |cjk: &mut Vec<char>, tokens: &mut Vec<String>| {
+        if let Some(last) = cjk.last() {
+            tokens.extend(cjk.windows(2).map(|w| w.iter().collect()));
+            tokens.push(last.to_string());
+        }
+        cjk.clear();
+    };
+
+    for c in normalize(text).chars() {
+        if is_cjk(c) {
+            if !word.is_empty() {
+                tokens.push(std::mem::take(&mut word));
+            }
+            cjk.push(c);
+        } else if c.is_alphanumeric() {
+            flush_cjk(&mut cjk, &mut tokens);
+            word.push(c);
+        } else {
+            flush_cjk(&mut cjk, &mut tokens);
+            if !word.is_empty() {
+                tokens.push(std::mem::take(&mut word));
+            }
+        }
+    }
+
+    flush_cjk(&mut cjk, &mut tokens);
+    if !word.is_empty() {
+        tokens.push(word);
+    }
+
+    tokens
+}
+
+#[inline]
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30ff}' // hiragana, katakana
+        | '\u{3400}'..='\u{4dbf}' // cjk extension a
+        | '\u{4e00}'..='\u{9fff}' // cjk unified ideographs
+        | '\u{ac00}'..='\u{d7af}' // hangul syllables
+        | '\u{f900}'..='\u{faff}' // cjk compatibility ideographs
+    )
+}
@@ -0,0 +1,151 @@
+use std::{cmp::Ordering, time::SystemTime};
+
+use crate::{
+    Movie,
+    db::{MovieData, normalize},
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SortKey {
+    #[default]
+    AddedTime,
+    ReleaseDate,
+    Title,
+    Num,
+    LastPlayed,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SortOrder {
+    Ascending,
+    #[default]
+    Descending,
+}
+
+impl SortOrder {
+    pub fn reverse(self) -> Self {
+        match self {
+            Self::Ascending => Self::Descending,
+            Self::Descending => Self::Ascending,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum SortValue {
+    Time(SystemTime),
+    Date(u32, u32, u32),
+    Text(Vec<Chunk>),
+}
+
+/// Part of a natural sort key, numbers sort before text and by their value.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Chunk {
+    // digit count first, so the digits compare like a number of any length
+    Num(usize, String),
+    Text(String),
+}
+
+impl SortKey {
+    fn value(&self, data: &MovieData) -> Option<SortValue> {
+        match self {
+            Self::AddedTime => Some(SortValue::Time(data.added_time)),
+            Self::ReleaseDate => {
+                release_date(&data.movie).map(|(y, m, d)| SortValue::Date(y, m, d))
+            }
+            Self::Title => {
+                // `sorttitle` is how the nfo wants to be sorted, e.g. without a leading article
+                [data.movie.sorttitle.as_deref(), Some(data.movie.title.as_str())]
+                    .into_iter()
+                    .flatten()
+                    .map(str::trim)
+                    .find(|t| !t.is_empty())
+                    .map(|t| SortValue::Text(natural_key(t)))
+            }
+            Self::Num => {
+                data.movie.num.as_deref()
+                    .map(str::trim)
+                    .filter(|n| !n.is_empty())
+                    .map(|n| SortValue::Text(natural_key(n)))
+            }
+            Self::LastPlayed => data.last_played.map(SortValue::Time),
+        }
+    }
+
+    /// Sorts positions of `movies`, entries without a value for the key come
+    /// last in either order. Ties are ordered by id, positions change when
+    /// entries move.
+    pub(crate) fn sort(&self, movies: &[MovieData], index: &[u32], order: SortOrder) -> Vec<u32> {
+        let mut keyed = vec![];
+        let mut missing = vec![];
+        for i in index.iter().copied() {
+            let Some(data) = movies.get(i as usize) else {
+                continue;
+            };
+
+            match self.value(data) {
+                Some(value) => keyed.push((value, &data.id, i)),
+                None => missing.push((&data.id, i)),
+            }
+        }
+
+        keyed.sort_unstable_by(|(a, a_id, _), (b, b_id, _)| {
+            let ord = match order {
+                SortOrder::Ascending => a.cmp(b),
+                SortOrder::Descending => b.cmp(a),
+            };
+            ord.then(a_id.cmp(b_id))
+        });
+        missing.sort_unstable();
+
+        keyed.into_iter().map(|(_, _, i)| i).chain(missing.into_iter().map(|(_, i)| i)).collect()
+    }
+}
+
+/// Release date as (year, month, day) from `releasedate`, Kodi's `premiered`
+/// or just the `year`. Dates like `2020-01-02`, `2020/1/2` or `2020` are accepted.
+pub fn release_date(movie: &Movie) -> Option<(u32, u32, u32)> {
+    [&movie.releasedate, &movie.premiered]
+        .into_iter()
+        .find_map(|date| parse_date(date.as_deref()?))
+        .or(movie.year.map(|year| (year, 0, 0)))
+}
+
+fn parse_date(date: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = date.split(|c: char| !c.is_ascii_digit()).filter(|p| !p.is_empty());
+
+    let year = parts.next().filter(|y| y.len() == 4)?.parse().ok()?;
+    let month = parts.next().and_then(|m| m.parse().ok()).unwrap_or(0);
+    let day = parts.next().and_then(|d| d.parse().ok()).unwrap_or(0);
+    Some((year, month, day))
+}
+
+/// Compares like a person would, `ABC-9` before `ABC-10`, case and accents
+/// are ignored so `Émile` sorts next to `emile`.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    natural_key(a).cmp(&natural_key(b))
+}
+
+fn natural_key(text: &str) -> Vec<Chunk> {
+    let text = normalize(text);
+    let mut chunks = vec![];
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let is_digit = c.is_ascii_digit();
+        let mut part = String::from(c);
+        while let Some(&next) = chars.peek() && next.is_ascii_digit() == is_digit {
+            part.push(next);
+            chars.next();
+        }
+
+        chunks.push(if is_digit {
+            let digits = part.trim_start_matches('0');
+            Chunk::Num(digits.len(), digits.to_string())
+        } else {
+            Chunk::Text(part)
+        });
+    }
+
+    chunks
+}
@@ -0,0 +1,308 @@
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+    time::{Duration, SystemTime},
+};
+
+use ahash::AHashMap;
+use anyhow::Result;
+use rusqlite::{Connection, OptionalExtension, Params, params};
+
+use serde_json::Value;
+
+use crate::db::{Config, JsonStorage, MovieData, MovieId, Storage, StorageQuery, import, migrate, normalize};
+
+// bumped when the tables change, stored as the `user_version` of the database
+const LAYOUT_VERSION: u32 = 2;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS meta (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS movies (
+    id TEXT PRIMARY KEY,
+    pos INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    -- `normalize`d title, queries compare like the linear scan does
+    title_key TEXT NOT NULL,
+    fav INTEGER NOT NULL,
+    added_time INTEGER NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS movies_pos ON movies (pos);
+CREATE INDEX IF NOT EXISTS movies_fav ON movies (fav);
+CREATE INDEX IF NOT EXISTS movies_added_time ON movies (added_time);
+CREATE TABLE IF NOT EXISTS actors (
+    id TEXT NOT NULL,
+    name TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS actors_id ON actors (id);
+CREATE INDEX IF NOT EXISTS actors_name ON actors (name);
+CREATE TABLE IF NOT EXISTS tags (
+    id TEXT NOT NULL,
+    name TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS tags_id ON tags (id);
+CREATE INDEX IF NOT EXISTS tags_name ON tags (name);
+";
+
+/// Stores every movie as its own row keyed by its [`MovieId`], so updating
+/// one entry doesn't rewrite the whole library. Searchable fields get their
+/// own indexed columns.
+#[derive(Debug)]
+pub struct SqliteStorage {
+    conn: Connection,
+    // what each stored row holds, `None` until the rows were read
+    rows: Mutex<Option<AHashMap<MovieId, Row>>>,
+}
+
+/// A stored movie row, a flush only writes the rows that differ.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Row {
+    pos: u32,
+    digest: u64,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let storage = Self {
+            conn: Connection::open(path)?,
+            rows: Mutex::new(None),
+        };
+        storage.upgrade()?;
+        Ok(storage)
+    }
+
+    /// Creates the tables. Rows of an older layout, e.g. keyed by their path,
+    /// are read with the old tables and written again.
+    fn upgrade(&self) -> Result<()> {
+        let version: u32 = self.conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version >= LAYOUT_VERSION {
+            return Ok(());
+        }
+
+        let has_movies = self
+            .conn
+            .query_row("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'movies'", [], |_| Ok(()))
+            .optional()?
+            .is_some();
+        let legacy = if has_movies { self.load()? } else { None };
+
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute_batch("DROP TABLE IF EXISTS movies; DROP TABLE IF EXISTS actors; DROP TABLE IF EXISTS tags;")?;
+        tx.execute_batch(SCHEMA)?;
+        if let Some(config) = legacy {
+            Self::write(&tx, &config, &AHashMap::new())?;
+        }
+        tx.pragma_update(None, "user_version", LAYOUT_VERSION)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Opens the default database, importing an existing `kr.json` on first use.
+    pub fn open_default() -> Result<Self> {
+        let storage = Self::open(&Self::default_path())?;
+        import(&JsonStorage::default(), &storage)?;
+        Ok(storage)
+    }
+
+    #[inline]
+    pub fn default_path() -> PathBuf {
+        dirs::DIR.config_local_dir().join("kr.sqlite")
+    }
+
+    /// Writes the rows of `config` that differ from `stored`, removes the rows
+    /// of entries that are gone and returns what is stored now.
+    fn write(conn: &Connection, config: &Config, stored: &AHashMap<MovieId, Row>) -> Result<AHashMap<MovieId, Row>> {
+        let mut rows = AHashMap::with_capacity(config.movies.len());
+        for (pos, item) in config.movies.iter().enumerate() {
+            let data = serde_json::to_string(item)?;
+            let row = Row {
+                pos: pos as u32,
+                digest: digest(&data),
+            };
+
+            match stored.get(&item.id) {
+                Some(old) if *old == row => {}
+                // only moved, e.g. an entry before it was removed
+                Some(old) if old.digest == row.digest => {
+                    conn.execute("UPDATE movies SET pos = ?1 WHERE id = ?2", params![pos as i64, item.id.as_str()])?;
+                }
+                _ => Self::write_movie(conn, pos, item, &data)?,
+            }
+            rows.insert(item.id.clone(), row);
+        }
+
+        for id in stored.keys().filter(|id| !rows.contains_key(*id)) {
+            for table in ["movies", "actors", "tags"] {
+                conn.execute(&format!("DELETE FROM {table} WHERE id = ?1"), [id.as_str()])?;
+            }
+        }
+
+        // everything but the movies themselves
+        let head = Config {
+            version: config.version,
+            movies: vec![],
+            roots: config.roots.clone(),
+            shows: config.shows.clone(),
+            video_extensions: config.video_extensions.clone(),
+        };
+        conn.execute(
+            "INSERT OR REPLACE INTO meta (key, value) VALUES ('config', ?1)",
+            [serde_json::to_string(&head)?],
+        )?;
+
+        Ok(rows)
+    }
+
+    fn write_movie(conn: &Connection, pos: usize, item: &MovieData, data: &str) -> Result<()> {
+        let id = item.id.as_str();
+        conn.execute(
+            "INSERT OR REPLACE INTO movies (id, pos, title, title_key, fav, added_time, data)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                id,
+                pos as i64,
+                item.movie.title,
+                normalize(&item.movie.title),
+                item.fav,
+                unix_secs(item.added_time),
+                data
+            ],
+        )?;
+
+        conn.execute("DELETE FROM actors WHERE id = ?1", [id])?;
+        for actor in &item.movie.actor {
+            conn.execute("INSERT INTO actors (id, name) VALUES (?1, ?2)", params![id, actor.name])?;
+        }
+
+        conn.execute("DELETE FROM tags WHERE id = ?1", [id])?;
+        for tag in item.movie.tag.iter().flatten() {
+            conn.execute("INSERT INTO tags (id, name) VALUES (?1, ?2)", params![id, tag])?;
+        }
+
+        Ok(())
+    }
+
+    /// What the stored rows hold, read from the database.
+    fn stored_rows(&self) -> Result<AHashMap<MovieId, Row>> {
+        let mut stmt = self.conn.prepare("SELECT id, pos, data FROM movies")?;
+        let rows = stmt.query_map([], |row| {
+            let id: String = row.get(0)?;
+            let pos: i64 = row.get(1)?;
+            let data: String = row.get(2)?;
+            Ok((id, pos, data))
+        })?;
+
+        rows.map(|row| {
+            let (id, pos, data) = row?;
+            Ok((MovieId::from_stored(id), Row { pos: pos as u32, digest: digest(&data) }))
+        })
+        .collect()
+    }
+
+    fn positions(&self, sql: &str, params: impl Params) -> Result<Vec<u32>> {
+        let mut stmt = self.conn.prepare(sql)?;
+        let rows = stmt.query_map(params, |row| row.get::<_, i64>(0))?;
+        rows.map(|pos| Ok(pos? as u32)).collect()
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn load(&self) -> Result<Option<Config>> {
+        let head: Option<String> = self
+            .conn
+            .query_row("SELECT value FROM meta WHERE key = 'config'", [], |row| row.get(0))
+            .optional()?;
+
+        let Some(head) = head else {
+            return Ok(None);
+        };
+
+        // rows are stored in the shape of their schema version, migrate them with the rest
+        let mut config: Value = serde_json::from_str(&head)?;
+        let mut stmt = self.conn.prepare("SELECT data FROM movies ORDER BY pos")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        let movies = rows
+            .map(|data| Ok(serde_json::from_str(&data?)?))
+            .collect::<Result<Vec<Value>>>()?;
+        config["movies"] = Value::Array(movies);
+
+        Ok(Some(migrate(config)?))
+    }
+
+    fn update(&self, config: &Config, id: &MovieId) -> Result<()> {
+        let Some((pos, item)) = config.movies.iter().enumerate().find(|(_, d)| &d.id == id) else {
+            return Ok(());
+        };
+
+        let data = serde_json::to_string(item)?;
+        let tx = self.conn.unchecked_transaction()?;
+        Self::write_movie(&tx, pos, item, &data)?;
+        tx.commit()?;
+
+        if let Some(rows) = self.rows.lock().unwrap_or_else(PoisonError::into_inner).as_mut() {
+            rows.insert(id.clone(), Row { pos: pos as u32, digest: digest(&data) });
+        }
+        Ok(())
+    }
+
+    fn flush(&self, config: &Config) -> Result<()> {
+        let mut rows = self.rows.lock().unwrap_or_else(PoisonError::into_inner);
+        // read again after a failed flush, the transaction was rolled back
+        let stored = match rows.take() {
+            Some(stored) => stored,
+            None => self.stored_rows()?,
+        };
+
+        let tx = self.conn.unchecked_transaction()?;
+        let written = Self::write(&tx, config, &stored)?;
+        tx.commit()?;
+
+        *rows = Some(written);
+        Ok(())
+    }
+
+    fn query(&self, _config: &Config, query: &StorageQuery) -> Result<Vec<u32>> {
+        use StorageQuery::*;
+        match query {
+            Title(title) => self.positions(
+                "SELECT pos FROM movies WHERE instr(title_key, ?1) > 0 ORDER BY pos",
+                [normalize(title)],
+            ),
+            Actor(name) => self.positions(
+                "SELECT DISTINCT m.pos FROM movies m JOIN actors a ON a.id = m.id WHERE a.name = ?1 ORDER BY m.pos",
+                [name],
+            ),
+            Tag(tag) => self.positions(
+                "SELECT DISTINCT m.pos FROM movies m JOIN tags t ON t.id = m.id WHERE t.name = ?1 ORDER BY m.pos",
+                [tag],
+            ),
+            Fav => self.positions("SELECT pos FROM movies WHERE fav = 1 ORDER BY pos", []),
+            AddedAfter(time) => self.positions(
+                "SELECT pos FROM movies WHERE added_time > ?1 ORDER BY pos",
+                [unix_secs(*time)],
+            ),
+        }
+    }
+}
+
+#[inline]
+fn unix_secs(time: SystemTime) -> i64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs() as i64
+}
+
+#[inline]
+fn digest(data: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,74 @@
+use std::{fmt::Debug, time::SystemTime};
+
+use anyhow::Result;
+
+use crate::db::{Config, MovieData, MovieId, normalize};
+
+/// Persistence backend of the library.
+///
+/// The whole `Config` is kept in memory, a backend only has to store it and
+/// may answer queries faster than a linear scan over `Config.movies`.
+pub trait Storage: Debug + Send {
+    /// Reads the stored library, `None` when nothing has been stored yet.
+    fn load(&self) -> Result<Option<Config>>;
+
+    /// Persists the entry `id` of `config.movies`.
+    fn update(&self, config: &Config, id: &MovieId) -> Result<()>;
+
+    /// Persists the whole library.
+    fn flush(&self, config: &Config) -> Result<()>;
+
+    /// Positions in `config.movies` of the entries matching `query`, in ascending order.
+    fn query(&self, config: &Config, query: &StorageQuery) -> Result<Vec<u32>> {
+        Ok(config
+            .movies
+            .iter()
+            .enumerate()
+            .filter(|(_, d)| query.matches(d))
+            .map(|(i, _)| i as u32)
+            .collect())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum StorageQuery<'a> {
+    /// Substring of the title, case and accents are ignored like in [`normalize`].
+    Title(&'a str),
+    Actor(&'a str),
+    Tag(&'a str),
+    Fav,
+    AddedAfter(SystemTime),
+}
+
+impl StorageQuery<'_> {
+    pub fn matches(&self, data: &MovieData) -> bool {
+        use StorageQuery::*;
+        match self {
+            Title(title) => normalize(&data.movie.title).contains(&normalize(title)),
+            Actor(name) => data.movie.actor.iter().any(|a| a.name == *name),
+            Tag(tag) => data
+                .movie
+                .tag
+                .as_ref()
+                .map(|tags| tags.iter().any(|t| t == tag))
+                .unwrap_or(false),
+            Fav => data.fav,
+            AddedAfter(time) => data.added_time > *time,
+        }
+    }
+}
+
+/// Copies the library from `from` into `to` unless `to` already holds one.
+/// Returns whether anything was imported.
+pub fn import(from: &dyn Storage, to: &dyn Storage) -> Result<bool> {
+    if to.load()?.is_some() {
+        return Ok(false);
+    }
+
+    let Some(config) = from.load()? else {
+        return Ok(false);
+    };
+
+    to.flush(&config)?;
+    Ok(true)
+}
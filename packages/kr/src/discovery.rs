@@ -0,0 +1,148 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+/// Per directory ignore file, one glob per line relative to its directory.
+pub const IGNORE_FILE: &str = ".kkignore";
+const MOVIE_NFO: &str = "movie.nfo";
+
+/// How a library root lays out its movies. Shows are found by their
+/// `tvshow.nfo` whatever the strategy.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Strategy {
+    /// One folder per movie with an nfo named after the folder, `Movie (2009)/Movie (2009).nfo`.
+    #[default]
+    KodiFolder,
+    /// One folder per movie with a `movie.nfo` inside.
+    MovieNfo,
+    /// Every nfo is a movie, several may share a folder.
+    Flat,
+}
+
+impl Strategy {
+    /// Whether `file_name` in the folder `dir_name` is a movie nfo.
+    pub fn is_movie(&self, dir_name: &str, file_name: &str) -> bool {
+        match self {
+            Self::KodiFolder => file_name.starts_with(dir_name),
+            Self::MovieNfo => file_name.eq_ignore_ascii_case(MOVIE_NFO),
+            Self::Flat => true,
+        }
+    }
+}
+
+/// Discovery settings of a library root.
+///
+/// `include` and `exclude` are globs relative to the root, a pattern without
+/// a `/` matches at any depth like in `.gitignore`. With `include` set only
+/// matching nfo files are picked up, excluded folders are not entered at all.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Discovery {
+    #[serde(default)]
+    pub strategy: Strategy,
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+impl Discovery {
+    pub fn new(strategy: Strategy) -> Self {
+        Self {
+            strategy,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn rules(&self) -> io::Result<Rules> {
+        let include = match self.include.is_empty() {
+            true => None,
+            false => Some(glob_set(&self.include)?),
+        };
+
+        Ok(Rules {
+            strategy: self.strategy,
+            include,
+            exclude: glob_set(&self.exclude)?,
+        })
+    }
+}
+
+/// Compiled [`Discovery`] of a root.
+#[derive(Debug)]
+pub(crate) struct Rules {
+    pub strategy: Strategy,
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl Rules {
+    /// `path` is relative to the root.
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        self.exclude.is_match(path)
+    }
+
+    /// `path` is relative to the root.
+    pub fn is_included(&self, path: &Path) -> bool {
+        self.include.as_ref().is_none_or(|set| set.is_match(path))
+    }
+}
+
+/// Patterns of a `.kkignore`, matched against paths relative to its folder.
+#[derive(Clone, Debug)]
+pub(crate) struct IgnoreFile {
+    dir: PathBuf,
+    set: GlobSet,
+}
+
+impl IgnoreFile {
+    /// Reads the ignore file of `dir`, if there is one. Empty lines and lines
+    /// starting with `#` are skipped, an invalid pattern ignores nothing.
+    pub fn read(dir: &Path) -> Option<Self> {
+        let content = std::fs::read_to_string(dir.join(IGNORE_FILE)).ok()?;
+        let patterns: Vec<&str> = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect();
+
+        let set = glob_set(&patterns)
+            .inspect_err(|e| warn!("{:?} ignored: {e}", dir.join(IGNORE_FILE)))
+            .ok()?;
+
+        Some(Self {
+            dir: dir.to_owned(),
+            set,
+        })
+    }
+
+    pub fn is_match(&self, path: &Path) -> bool {
+        path.strip_prefix(&self.dir)
+            .map(|rel| self.set.is_match(rel))
+            .unwrap_or(false)
+    }
+}
+
+fn glob_set(patterns: &[impl AsRef<str>]) -> io::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let pattern = pattern.as_ref().trim().trim_end_matches('/');
+        let pattern = match pattern.strip_prefix('/') {
+            Some(anchored) => anchored.to_string(),
+            None if !pattern.contains('/') => format!("**/{pattern}"),
+            None => pattern.to_string(),
+        };
+
+        let glob = GlobBuilder::new(&pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        builder.add(glob);
+    }
+
+    builder.build().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
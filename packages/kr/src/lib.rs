@@ -1,34 +1,376 @@
-use serde::{Deserialize, Serialize};
+use std::{path::Path, str::FromStr};
 
-use crate::db::SimpleJsonDatabase;
+use serde::{Deserialize, Deserializer, Serialize, de::IgnoredAny};
+
+use crate::db::Database;
 
 pub mod db;
+pub mod discovery;
+pub mod nfo;
 pub mod util;
+pub mod video;
+pub mod watch;
 
+/// A movie `.nfo` in the Kodi schema, which Jellyfin, tinyMediaManager and
+/// most scrapers also write. Everything is optional, a missing title falls
+/// back to the file name in [`Movie::load`].
+///
+/// Episode nfo files (`<episodedetails>`) share the schema and fill in the
+/// episode fields as well.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Movie {
+    #[serde(default)]
     pub title: String,
+    pub originaltitle: Option<String>,
+    pub sorttitle: Option<String>,
     pub outline: Option<String>,
+    pub plot: Option<String>,
+    pub tagline: Option<String>,
     pub poster: Option<String>,
-    pub thumb: Option<String>,
-    pub fanart: Option<String>,
+    /// Kodi writes one `<thumb>` per artwork type, scrapers a single local file.
+    #[serde(default)]
+    pub thumb: Vec<Thumb>,
+    pub fanart: Option<Fanart>,
     pub label: Option<String>,
+    // episodes often leave the cast to tvshow.nfo
+    #[serde(default)]
     pub actor: Vec<Actor>,
     pub tag: Option<Vec<String>>,
     pub genre: Option<Vec<String>>,
     pub num: Option<String>,
     pub releasedate: Option<String>,
+    pub premiered: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub year: Option<u32>,
+    pub dateadded: Option<String>,
     pub cover: Option<String>,
     pub website: Option<String>,
+    /// Single rating of older nfo files, newer ones use `ratings`.
+    #[serde(default, deserialize_with = "lenient")]
+    pub rating: Option<f32>,
+    pub ratings: Option<Ratings>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub userrating: Option<f32>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub top250: Option<u32>,
+    /// In minutes.
+    #[serde(default, deserialize_with = "lenient")]
+    pub runtime: Option<u32>,
+    pub mpaa: Option<String>,
+    pub studio: Option<Vec<String>>,
+    pub director: Option<Vec<String>>,
+    pub credits: Option<Vec<String>>,
+    pub country: Option<Vec<String>>,
+    pub set: Option<MovieSet>,
+    pub uniqueid: Option<Vec<UniqueId>>,
+    /// Legacy id of older nfo files, usually an imdb id.
+    pub id: Option<String>,
+    pub trailer: Option<String>,
+    pub fileinfo: Option<FileInfo>,
+    pub showtitle: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub season: Option<u32>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub episode: Option<u32>,
+    pub aired: Option<String>,
+}
+
+impl Movie {
+    pub fn from_nfo(nfo: &str) -> Result<Self, quick_xml::DeError> {
+        quick_xml::de::from_str(&nfo::sanitize(nfo))
+    }
+
+    /// Reads an nfo in any encoding, the title defaults to the file name.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let mut movie = Self::from_nfo(&nfo::read(path)?)?;
+        if movie.title.trim().is_empty()
+            && let Some(stem) = path.file_stem()
+        {
+            movie.title = stem.to_string_lossy().into_owned();
+        }
+        movie.actor.retain(|a| !a.name.trim().is_empty());
+        Ok(movie)
+    }
+
+    /// Image for the grid, a landscape thumb is preferred over the poster.
+    pub fn thumb_path(&self) -> Option<&str> {
+        pick_thumb(self.thumb.iter()).or(self.poster.as_deref())
+    }
+
+    /// The rating marked as default, or the first one.
+    pub fn default_rating(&self) -> Option<f32> {
+        let ratings = self.ratings.as_ref().map(|r| r.rating.as_slice()).unwrap_or_default();
+        ratings
+            .iter()
+            .find(|r| r.default == Some(true))
+            .or(ratings.first())
+            .and_then(|r| r.value)
+            .or(self.rating)
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Actor {
+    #[serde(default)]
     pub name: String,
     pub role: Option<String>,
     pub thumb: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub order: Option<u32>,
+}
+
+/// `<thumb aspect="poster">poster.jpg</thumb>`, season artwork of a show
+/// carries `type="season"` and the season number.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Thumb {
+    #[serde(rename = "@aspect", skip_serializing_if = "Option::is_none")]
+    pub aspect: Option<String>,
+    #[serde(rename = "@type", skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(
+        rename = "@season",
+        default,
+        deserialize_with = "lenient",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub season: Option<u32>,
+    #[serde(rename = "@preview", skip_serializing_if = "Option::is_none")]
+    pub preview: Option<String>,
+    #[serde(rename = "$text", default)]
+    pub path: String,
+}
+
+impl Thumb {
+    fn is_season(&self) -> bool {
+        self.season.is_some() || self.kind.as_deref() == Some("season")
+    }
+}
+
+/// Prefers a landscape image since the grid tiles are wide.
+fn pick_thumb<'a>(thumbs: impl Iterator<Item = &'a Thumb> + Clone) -> Option<&'a str> {
+    let is_landscape = |t: &&Thumb| {
+        t.aspect.as_deref().is_none_or(|a| a == "landscape" || a == "thumb")
+    };
+
+    thumbs
+        .clone()
+        .find(is_landscape)
+        .or(thumbs.clone().next())
+        .map(|t| t.path.trim())
+        .filter(|p| !p.is_empty())
+}
+
+/// Either `<fanart>fanart.jpg</fanart>` or Kodi's `<fanart><thumb>..</thumb></fanart>`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Fanart {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub thumb: Vec<Thumb>,
+    #[serde(rename = "$text", skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Ratings {
+    #[serde(default)]
+    pub rating: Vec<Rating>,
+}
+
+/// `<rating name="imdb" max="10" default="true"><value>7.5</value><votes>100</votes></rating>`
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Rating {
+    #[serde(rename = "@name")]
+    pub name: Option<String>,
+    #[serde(rename = "@max", default, deserialize_with = "lenient")]
+    pub max: Option<f32>,
+    #[serde(rename = "@default", default, deserialize_with = "lenient")]
+    pub default: Option<bool>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub value: Option<f32>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub votes: Option<u64>,
+}
+
+/// `<uniqueid type="imdb" default="true">tt0000000</uniqueid>`
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct UniqueId {
+    #[serde(rename = "@type")]
+    pub kind: Option<String>,
+    #[serde(rename = "@default", default, deserialize_with = "lenient")]
+    pub default: Option<bool>,
+    #[serde(rename = "$text", default)]
+    pub value: String,
+}
+
+/// Either `<set>Name</set>` or `<set><name>Name</name><overview>..</overview></set>`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct MovieSet {
+    pub name: Option<String>,
+    pub overview: Option<String>,
+    #[serde(rename = "$text", skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+impl MovieSet {
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref().or(self.text.as_deref()).map(str::trim)
+    }
+}
+
+/// A `tvshow.nfo`, the episodes are separate nfo files below the show folder.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TvShow {
+    #[serde(default)]
+    pub title: String,
+    pub originaltitle: Option<String>,
+    pub sorttitle: Option<String>,
+    pub outline: Option<String>,
+    pub plot: Option<String>,
+    pub tagline: Option<String>,
+    #[serde(default)]
+    pub thumb: Vec<Thumb>,
+    pub fanart: Option<Fanart>,
+    #[serde(default)]
+    pub actor: Vec<Actor>,
+    pub tag: Option<Vec<String>>,
+    pub genre: Option<Vec<String>>,
+    pub premiered: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub year: Option<u32>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub rating: Option<f32>,
+    pub ratings: Option<Ratings>,
+    /// Episode runtime in minutes.
+    #[serde(default, deserialize_with = "lenient")]
+    pub runtime: Option<u32>,
+    pub mpaa: Option<String>,
+    pub studio: Option<Vec<String>>,
+    pub country: Option<Vec<String>>,
+    pub status: Option<String>,
+    pub uniqueid: Option<Vec<UniqueId>>,
+    pub id: Option<String>,
+    pub namedseason: Option<Vec<NamedSeason>>,
+}
+
+impl TvShow {
+    pub fn from_nfo(nfo: &str) -> Result<Self, quick_xml::DeError> {
+        quick_xml::de::from_str(&nfo::sanitize(nfo))
+    }
+
+    /// Reads a `tvshow.nfo` in any encoding, the title defaults to the show folder.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let mut show = Self::from_nfo(&nfo::read(path)?)?;
+        if show.title.trim().is_empty()
+            && let Some(dir) = path.parent().and_then(Path::file_name)
+        {
+            show.title = dir.to_string_lossy().into_owned();
+        }
+        show.actor.retain(|a| !a.name.trim().is_empty());
+        Ok(show)
+    }
+
+    pub fn thumb_path(&self) -> Option<&str> {
+        pick_thumb(self.thumb.iter().filter(|t| !t.is_season()))
+    }
+
+    pub fn season_thumb_path(&self, season: u32) -> Option<&str> {
+        pick_thumb(self.thumb.iter().filter(|t| t.season == Some(season)))
+    }
+
+    pub fn season_name(&self, season: u32) -> Option<&str> {
+        self.namedseason
+            .iter()
+            .flatten()
+            .find(|s| s.number == Some(season))
+            .map(|s| s.name.trim())
+            .filter(|name| !name.is_empty())
+    }
+}
+
+/// `<namedseason number="1">Name</namedseason>`
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct NamedSeason {
+    #[serde(rename = "@number", default, deserialize_with = "lenient")]
+    pub number: Option<u32>,
+    #[serde(rename = "$text", default)]
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct FileInfo {
+    pub streamdetails: Option<StreamDetails>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct StreamDetails {
+    #[serde(default)]
+    pub video: Vec<VideoStream>,
+    #[serde(default)]
+    pub audio: Vec<AudioStream>,
+    #[serde(default)]
+    pub subtitle: Vec<SubtitleStream>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct VideoStream {
+    pub codec: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub aspect: Option<f32>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub width: Option<u32>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub height: Option<u32>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub durationinseconds: Option<u32>,
+    pub stereomode: Option<String>,
+    pub hdrtype: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct AudioStream {
+    pub codec: Option<String>,
+    pub language: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub channels: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SubtitleStream {
+    pub language: Option<String>,
+}
+
+/// Numbers and flags that are empty or malformed in the wild become `None`
+/// instead of failing the whole nfo.
+fn lenient<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr + Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Lenient<T> {
+        Value(T),
+        Text(String),
+        // an xml element read without a type hint
+        Element {
+            #[serde(rename = "$text")]
+            text: String,
+        },
+        Other(IgnoredAny),
+    }
+
+    Ok(match Option::<Lenient<T>>::deserialize(deserializer)? {
+        Some(Lenient::Value(value)) => Some(value),
+        Some(Lenient::Text(text) | Lenient::Element { text }) => text.trim().parse().ok(),
+        Some(Lenient::Other(_)) | None => None,
+    })
+}
+
+pub fn init( ) -> Database {
+    Database::default()
 }
 
-pub fn init( ) -> SimpleJsonDatabase {
-    SimpleJsonDatabase::default()
+#[cfg(feature = "sqlite")]
+pub fn init_sqlite() -> anyhow::Result<Database> {
+    let storage = db::SqliteStorage::open_default()?;
+    Ok(Database::with_storage(Box::new(storage)))
 }
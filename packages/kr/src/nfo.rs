@@ -0,0 +1,228 @@
+use std::{borrow::Cow, fmt::Write, path::Path};
+
+use anyhow::Result;
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8};
+
+/// Reads an nfo file in whatever encoding it was saved with.
+pub fn read(path: &Path) -> Result<String> {
+    let bytes = std::fs::read(path)?;
+    Ok(decode(&bytes))
+}
+
+/// Decodes by BOM, as UTF-8, by the encoding in the xml declaration and
+/// finally by guessing, Shift-JIS and GBK files rarely declare theirs.
+pub fn decode(bytes: &[u8]) -> String {
+    if let Some((encoding, bom_len)) = Encoding::for_bom(bytes) {
+        return encoding.decode_without_bom_handling(&bytes[bom_len..]).0.into_owned();
+    }
+
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return text.to_owned();
+    }
+
+    // a file that claims UTF-8 but isn't has to be guessed as well
+    let encoding = declared_encoding(bytes)
+        .filter(|encoding| *encoding != UTF_8)
+        .unwrap_or_else(|| {
+            let mut detector = EncodingDetector::new();
+            detector.feed(bytes, true);
+            detector.guess(None, true)
+        });
+
+    encoding.decode_without_bom_handling(bytes).0.into_owned()
+}
+
+/// `<?xml version="1.0" encoding="Shift_JIS"?>`, the declaration is ascii in
+/// every encoding that can be declared this way.
+fn declared_encoding(bytes: &[u8]) -> Option<&'static Encoding> {
+    let end = bytes.iter().position(|b| *b == b'>')?;
+    let declaration = String::from_utf8_lossy(&bytes[..end]);
+    if !declaration.trim_start().starts_with("<?xml") {
+        return None;
+    }
+
+    let rest = &declaration[declaration.find("encoding=")? + "encoding=".len()..];
+    let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let label = rest[1..].split(quote).next()?;
+    Encoding::for_label(label.trim().as_bytes())
+}
+
+/// Repairs what scrapers commonly write but xml doesn't allow: html entities
+/// like `&nbsp;`, a bare `&` and `<br>` inside text.
+pub fn sanitize(xml: &str) -> Cow<'_, str> {
+    if !xml.contains('&') && !xml.contains("<br") && !xml.contains("<BR") {
+        return Cow::Borrowed(xml);
+    }
+
+    let mut out = String::with_capacity(xml.len());
+    let mut rest = xml;
+    while let Some(i) = rest.find(['&', '<']) {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+
+        if rest.starts_with('<') {
+            // cdata and comments are copied as they are
+            let verbatim = [("<![CDATA[", "]]>"), ("<!--", "-->")]
+                .into_iter()
+                .find(|(start, _)| rest.starts_with(start))
+                .map(|(_, end)| rest.find(end).map(|i| i + end.len()).unwrap_or(rest.len()));
+
+            let len = if let Some(len) = verbatim {
+                out.push_str(&rest[..len]);
+                len
+            } else if let Some(len) = line_break_len(rest) {
+                out.push('\n');
+                len
+            } else {
+                out.push('<');
+                1
+            };
+            rest = &rest[len..];
+            continue;
+        }
+
+        let name = rest[1..]
+            .find(';')
+            .map(|end| &rest[1..end + 1])
+            .filter(|name| is_entity_name(name));
+
+        match name {
+            Some(name) if is_xml_entity(name) => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+            Some(name) if let Some(c) = html_entity(name) => {
+                let _ = write!(out, "&#{};", c as u32);
+                rest = &rest[name.len() + 2..];
+            }
+            _ => {
+                out.push_str("&amp;");
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+
+    Cow::Owned(out)
+}
+
+/// Length of a `<br>`, `<br/>` or `<BR />` tag at the start of `text`.
+fn line_break_len(text: &str) -> Option<usize> {
+    let tag = text.get(..3)?;
+    if !tag.eq_ignore_ascii_case("<br") {
+        return None;
+    }
+
+    let rest = text[3..].trim_start();
+    let rest = rest.strip_prefix('/').unwrap_or(rest);
+    rest.strip_prefix('>').map(|after| text.len() - after.len())
+}
+
+#[inline]
+fn is_entity_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= 32 && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '#')
+}
+
+fn is_xml_entity(name: &str) -> bool {
+    if let Some(code) = name.strip_prefix('#') {
+        return match code.strip_prefix(['x', 'X']) {
+            Some(hex) => !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()),
+            None => !code.is_empty() && code.chars().all(|c| c.is_ascii_digit()),
+        };
+    }
+
+    matches!(name, "amp" | "lt" | "gt" | "quot" | "apos")
+}
+
+/// The html entities that show up in scraped plots and titles.
+fn html_entity(name: &str) -> Option<char> {
+    let c = match name {
+        "nbsp" => '\u{a0}',
+        "ensp" => '\u{2002}',
+        "emsp" => '\u{2003}',
+        "thinsp" => '\u{2009}',
+        "shy" => '\u{ad}',
+        "hellip" => '…',
+        "mdash" => '—',
+        "ndash" => '–',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "sbquo" => '‚',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "bdquo" => '„',
+        "laquo" => '«',
+        "raquo" => '»',
+        "middot" => '·',
+        "bull" => '•',
+        "prime" => '′',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "deg" => '°',
+        "times" => '×',
+        "divide" => '÷',
+        "plusmn" => '±',
+        "frac12" => '½',
+        "frac14" => '¼',
+        "frac34" => '¾',
+        "sup2" => '²',
+        "sup3" => '³',
+        "micro" => 'µ',
+        "sect" => '§',
+        "para" => '¶',
+        "iexcl" => '¡',
+        "iquest" => '¿',
+        "cent" => '¢',
+        "pound" => '£',
+        "yen" => '¥',
+        "euro" => '€',
+        "hearts" => '♥',
+        "aacute" => 'á',
+        "Aacute" => 'Á',
+        "agrave" => 'à',
+        "Agrave" => 'À',
+        "acirc" => 'â',
+        "auml" => 'ä',
+        "Auml" => 'Ä',
+        "aring" => 'å',
+        "Aring" => 'Å',
+        "aelig" => 'æ',
+        "atilde" => 'ã',
+        "ccedil" => 'ç',
+        "Ccedil" => 'Ç',
+        "eacute" => 'é',
+        "Eacute" => 'É',
+        "egrave" => 'è',
+        "Egrave" => 'È',
+        "ecirc" => 'ê',
+        "euml" => 'ë',
+        "iacute" => 'í',
+        "igrave" => 'ì',
+        "icirc" => 'î',
+        "iuml" => 'ï',
+        "ntilde" => 'ñ',
+        "Ntilde" => 'Ñ',
+        "oacute" => 'ó',
+        "Oacute" => 'Ó',
+        "ograve" => 'ò',
+        "ocirc" => 'ô',
+        "otilde" => 'õ',
+        "ouml" => 'ö',
+        "Ouml" => 'Ö',
+        "oslash" => 'ø',
+        "Oslash" => 'Ø',
+        "uacute" => 'ú',
+        "ugrave" => 'ù',
+        "ucirc" => 'û',
+        "uuml" => 'ü',
+        "Uuml" => 'Ü',
+        "yacute" => 'ý',
+        "yuml" => 'ÿ',
+        "szlig" => 'ß',
+        _ => return None,
+    };
+
+    Some(c)
+}
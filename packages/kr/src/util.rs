@@ -1,99 +1,266 @@
 use std::{
-    fs::{self, DirEntry},
+    collections::BTreeMap,
+    fs::{self, Metadata},
     path::{Path, PathBuf},
     time::SystemTime,
 };
 
 use ahash::AHashSet;
+use log::debug;
+use serde::{Deserialize, Serialize};
 
-pub fn find_new_movie_nfo(
-    root: &Path,
-    last_scan_time: SystemTime,
-    known_files: &AHashSet<PathBuf>,
-) -> std::io::Result<Vec<PathBuf>> {
-    let mut new_files = Vec::new();
-
-    let entries = fs::read_dir(root)?;
-    let mut entries: Vec<DirEntry> = entries.into_iter().flatten().collect();
-
-    // hdd friendly
-    entries.sort_by_key(|e| {
-        e.metadata()
-            .map(|_m| {
-                #[cfg(unix)]
-                {
-                    use std::os::unix::fs::MetadataExt;
-                    _m.ino()
-                }
+use crate::discovery::{Discovery, IGNORE_FILE, IgnoreFile, Rules};
 
-                #[cfg(not(any(unix)))]
-                {
-                    0
-                }
-            })
-            .unwrap_or(0)
-    });
+pub const SHOW_NFO: &str = "tvshow.nfo";
+// per season metadata, not an episode
+const SEASON_NFO: &str = "season.nfo";
 
-    for entry in entries {
-        get_nfo_files(&mut new_files, entry, known_files, last_scan_time);
-    }
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NfoKind {
+    Movie,
+    Show,
+    /// An episode below the show whose `tvshow.nfo` is given.
+    Episode(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+pub struct NfoFile {
+    pub path: PathBuf,
+    pub kind: NfoKind,
+}
+
+/// A directory as it was when it was last read. The mtime of a directory
+/// only changes with its direct entries, so an unchanged directory is not
+/// read again but its subdirectories are still visited.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct DirState {
+    pub mtime: Option<SystemTime>,
+    pub inode: u64,
+    pub subdirs: Vec<String>,
+    pub nfos: Vec<String>,
+    /// Whether the directory has a `.kkignore`.
+    pub ignore: bool,
+}
+
+/// Scan state of a root by directory relative to it, the root itself is `""`.
+pub type DirStates = BTreeMap<PathBuf, DirState>;
+
+/// Result of a walk over a root.
+#[derive(Debug, Default)]
+pub struct NfoWalk {
+    /// Nfo files that are not known yet.
+    pub found: Vec<NfoFile>,
+    /// Known files the discovery rules still pick up. Known files below a
+    /// directory that could not be read are kept as well.
+    pub kept: AHashSet<PathBuf>,
+}
 
-    Ok(new_files)
+struct Walk<'a> {
+    root: &'a Path,
+    rules: Rules,
+    known_files: &'a AHashSet<PathBuf>,
+    old: &'a DirStates,
+    new: DirStates,
+    found: Vec<NfoFile>,
+    kept: AHashSet<PathBuf>,
+    progress: &'a mut dyn FnMut(usize, usize),
 }
 
-fn get_nfo_files(
-    buf: &mut Vec<PathBuf>,
-    entry: DirEntry,
+/// Finds nfo files that are not known yet. Which files are movies depends on
+/// the strategy of `discovery`, every nfo below a folder with a `tvshow.nfo` is
+/// an episode. Invalid globs fail with `InvalidInput`.
+///
+/// Only directories that changed since `dirs` was recorded are read, `dirs` is
+/// replaced by the state of this walk. Pass an empty map to read everything.
+pub fn find_new_nfo(
+    root: &Path,
+    discovery: &Discovery,
+    dirs: &mut DirStates,
     known_files: &AHashSet<PathBuf>,
-    last_scan_time: SystemTime,
-) {
-    let path = entry.path();
+) -> std::io::Result<Vec<NfoFile>> {
+    Ok(find_new_nfo_with_progress(root, discovery, dirs, known_files, &mut |_, _| {})?.found)
+}
 
-    let Ok(metadata) = entry.metadata() else {
-        return;
+/// [`find_new_nfo`] that calls `progress` after every directory with the
+/// number of directories (always 1) and new nfo files it added. Also tells
+/// which of `known_files` are still picked up, see [`NfoWalk`].
+pub fn find_new_nfo_with_progress(
+    root: &Path,
+    discovery: &Discovery,
+    dirs: &mut DirStates,
+    known_files: &AHashSet<PathBuf>,
+    progress: &mut dyn FnMut(usize, usize),
+) -> std::io::Result<NfoWalk> {
+    let metadata = fs::metadata(root)?;
+    let mut walk = Walk {
+        root,
+        rules: discovery.rules()?,
+        known_files,
+        old: dirs,
+        new: DirStates::new(),
+        found: vec![],
+        kept: AHashSet::new(),
+        progress,
     };
 
-    let Ok(dir_mtime) = metadata.modified() else {
-        return;
-    };
+    walk.visit(root, &metadata, &[], None)?;
+    let Walk { new, found, kept, .. } = walk;
+    *dirs = new;
+    Ok(NfoWalk { found, kept })
+}
+
+impl Walk<'_> {
+    fn visit(
+        &mut self,
+        dir: &Path,
+        metadata: &Metadata,
+        ignores: &[IgnoreFile],
+        show: Option<&Path>,
+    ) -> std::io::Result<()> {
+        let rel_dir = dir.strip_prefix(self.root).unwrap_or(dir).to_owned();
+        let mtime = metadata.modified().ok();
+        let inode = inode(metadata);
+
+        let state = match self.old.get(&rel_dir) {
+            Some(old) if mtime.is_some() && old.mtime == mtime && old.inode == inode => old.clone(),
+            _ => read_dir_state(dir, mtime, inode)?,
+        };
+
+        let nested;
+        let ignores = match state.ignore.then(|| IgnoreFile::read(dir)).flatten() {
+            Some(ignore) => {
+                nested = [ignores, &[ignore]].concat();
+                &nested
+            }
+            None => ignores,
+        };
+
+        // the outermost show wins, a season folder can't start a new show
+        let show_path = dir.join(SHOW_NFO);
+        let show = show
+            .map(Path::to_owned)
+            .or_else(|| state.nfos.iter().any(|n| n == SHOW_NFO).then_some(show_path));
+
+        let found = self.found.len();
+        let dir_name = dir.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+        for file_name in &state.nfos {
+            let path = dir.join(file_name);
+            if self.is_excluded(&path, ignores) {
+                continue;
+            }
+
+            if !self.rules.is_included(self.rel(&path)) {
+                debug!("{path:?} not included");
+                continue;
+            }
 
-    if dir_mtime <= last_scan_time {
-        return;
+            let kind = if file_name == SHOW_NFO {
+                NfoKind::Show
+            } else if let Some(show) = &show {
+                if file_name == SEASON_NFO {
+                    continue;
+                }
+                NfoKind::Episode(show.clone())
+            } else if self.rules.strategy.is_movie(&dir_name, file_name) {
+                NfoKind::Movie
+            } else {
+                debug!("{path:?} is not a movie nfo for {:?}", self.rules.strategy);
+                continue;
+            };
+
+            if self.known_files.contains(&path) {
+                self.kept.insert(path);
+                continue;
+            }
+
+            self.found.push(NfoFile { path, kind });
+        }
+        (self.progress)(1, self.found.len() - found);
+
+        for name in &state.subdirs {
+            let path = dir.join(name);
+            if self.is_excluded(&path, ignores) {
+                continue;
+            }
+
+            // gone since the directory was recorded, or not a directory anymore
+            let Ok(metadata) = fs::symlink_metadata(&path) else {
+                continue;
+            };
+
+            if metadata.is_dir()
+                && let Err(e) = self.visit(&path, &metadata, ignores, show.as_deref())
+            {
+                debug!("{path:?} skipped: {e}");
+                // unreadable for now, that doesn't make its entries unwanted
+                let below = self.known_files.iter().filter(|known| known.starts_with(&path));
+                self.kept.extend(below.cloned());
+            }
+        }
+
+        self.new.insert(rel_dir, state);
+        Ok(())
     }
 
-    let Some(p) = path.parent() else {
-        return;
-    };
+    #[inline]
+    fn rel<'p>(&self, path: &'p Path) -> &'p Path {
+        path.strip_prefix(self.root).unwrap_or(path)
+    }
 
-    let Some(dir_name) = p.file_name() else {
-        return;
-    };
+    fn is_excluded(&self, path: &Path, ignores: &[IgnoreFile]) -> bool {
+        let excluded = self.rules.is_excluded(self.rel(path))
+            || ignores.iter().any(|ignore| ignore.is_match(path));
+        if excluded {
+            debug!("{path:?} excluded");
+        }
+        excluded
+    }
+}
 
-    let Some(file_name) = path.file_name() else {
-        return;
+/// Reads the entries of `dir`, ordered by inode to keep hdd seeks short.
+fn read_dir_state(dir: &Path, mtime: Option<SystemTime>, inode: u64) -> std::io::Result<DirState> {
+    let mut entries: Vec<_> = fs::read_dir(dir)?
+        .flatten()
+        .filter_map(|e| Some((e.metadata().ok()?, e.file_name())))
+        .collect();
+    entries.sort_by_key(|(m, _)| self::inode(m));
+
+    let mut state = DirState {
+        mtime,
+        inode,
+        ..Default::default()
     };
 
-    let dir_name = dir_name.to_string_lossy();
-    let file_name = file_name.to_string_lossy();
+    for (metadata, name) in entries {
+        let Ok(name) = name.into_string() else {
+            debug!("{dir:?} has an entry that is not valid unicode, skipped");
+            continue;
+        };
 
-    let file_type = metadata.file_type();
-    if file_type.is_file()
-        && file_name.starts_with(dir_name.as_ref())
-        && is_nfo_file(known_files, &path)
-    {
-        buf.push(path);
-    } else if file_type.is_dir()
-        && let Ok(sub_entries) = fs::read_dir(&path)
-    {
-        for sub_entry in sub_entries.into_iter().flatten() {
-            get_nfo_files(buf, sub_entry, known_files, last_scan_time);
+        if metadata.is_dir() {
+            state.subdirs.push(name);
+        } else if metadata.is_file() {
+            if name == IGNORE_FILE {
+                state.ignore = true;
+            } else if Path::new(&name).extension().is_some_and(|ext| ext == "nfo") {
+                state.nfos.push(name);
+            }
         }
     }
+
+    Ok(state)
 }
 
 #[inline]
-fn is_nfo_file(known_files: &AHashSet<PathBuf>, path: &Path) -> bool {
-    path.extension()
-        .map(|ext| ext == "nfo" && !known_files.contains(path))
-        .unwrap_or(false)
+fn inode(_metadata: &Metadata) -> u64 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        _metadata.ino()
+    }
+
+    #[cfg(not(any(unix)))]
+    {
+        0
+    }
 }
@@ -0,0 +1,136 @@
+use std::{
+    ffi::OsStr,
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Video extensions of a new library. When a movie exists in several formats
+/// the one listed first is played.
+pub const DEFAULT_EXTENSIONS: [&str; 16] = [
+    "mp4", "mkv", "avi", "rmvb", "webm", "m4v", "mov", "wmv", "ts", "m2ts", "mpg", "mpeg", "flv", "ogv", "rm", "3gp",
+];
+
+// words marking one part of a release split over several files
+const PART_WORDS: [&str; 6] = ["cd", "dvd", "part", "pt", "disc", "disk"];
+const SEPARATORS: [char; 4] = [' ', '.', '-', '_'];
+
+struct Video {
+    stem: String,
+    rank: usize,
+    path: PathBuf,
+}
+
+/// The video files of the nfo at `nfo` in playing order, several for a
+/// multi-part release and none when there is no video next to it.
+///
+/// `Movie.nfo` goes with `Movie.mkv`, or with the parts `Movie-cd1.mkv`,
+/// `Movie-cd2.mkv`. A generic `movie.nfo` takes the video of its folder,
+/// the largest one if there are several and trailers and samples are skipped.
+pub fn find_videos(nfo: &Path, extensions: &[String]) -> io::Result<Vec<PathBuf>> {
+    let Some(dir) = nfo.parent() else {
+        return Ok(vec![]);
+    };
+
+    let mut videos = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(rank) = extension_rank(&path, extensions) else {
+            continue;
+        };
+
+        if let Some(stem) = path.file_stem().and_then(OsStr::to_str)
+            && path.is_file()
+        {
+            videos.push(Video { stem: stem.to_string(), rank, path });
+        }
+    }
+
+    let stem = nfo.file_stem().and_then(OsStr::to_str).unwrap_or_default();
+    if stem.eq_ignore_ascii_case("movie") {
+        return Ok(folder_videos(videos));
+    }
+
+    // an nfo named after the first part still finds the others
+    let base = split_part(stem).map(|(base, _)| base).unwrap_or(stem);
+    Ok(named_videos(base, videos))
+}
+
+/// Splits the part number off a file name, `Movie-cd1` is part 1 of `Movie`.
+/// The part word has to be separated from the name by a space, `.`, `-` or `_`.
+pub fn split_part(stem: &str) -> Option<(&str, u32)> {
+    let rest = stem.trim_end_matches(|c: char| c.is_ascii_digit());
+    let num = stem[rest.len()..].parse().ok()?;
+
+    let rest = rest.trim_end_matches(SEPARATORS);
+    let lower = rest.to_ascii_lowercase();
+    let word = PART_WORDS.iter().find(|word| lower.ends_with(*word))?;
+
+    let name = &rest[..rest.len() - word.len()];
+    let base = name.trim_end_matches(SEPARATORS);
+    if base.is_empty() || base.len() == name.len() {
+        return None;
+    }
+
+    Some((base, num))
+}
+
+fn extension_rank(path: &Path, extensions: &[String]) -> Option<usize> {
+    let ext = path.extension()?.to_str()?;
+    extensions
+        .iter()
+        .position(|e| e.trim_start_matches('.').eq_ignore_ascii_case(ext))
+}
+
+#[inline]
+fn same_name(a: &str, b: &str) -> bool {
+    a == b || a.to_lowercase() == b.to_lowercase()
+}
+
+/// The video named `base`, or else its parts ordered by part number.
+fn named_videos(base: &str, videos: Vec<Video>) -> Vec<PathBuf> {
+    if let Some(video) = videos
+        .iter()
+        .filter(|v| same_name(&v.stem, base))
+        .min_by_key(|v| v.rank)
+    {
+        return vec![video.path.clone()];
+    }
+
+    let mut parts: Vec<(u32, usize, PathBuf)> = videos
+        .into_iter()
+        .filter_map(|v| {
+            let (name, num) = split_part(&v.stem)?;
+            same_name(name, base).then_some((num, v.rank, v.path))
+        })
+        .collect();
+
+    // one file per part, in the preferred format
+    parts.sort();
+    parts.dedup_by_key(|(num, ..)| *num);
+    parts.into_iter().map(|(.., path)| path).collect()
+}
+
+fn folder_videos(mut videos: Vec<Video>) -> Vec<PathBuf> {
+    videos.retain(|v| {
+        let stem = v.stem.to_lowercase();
+        !stem.ends_with("-trailer") && !stem.contains("sample")
+    });
+
+    // a folder holding nothing but the parts of one release
+    let bases: Vec<String> = videos
+        .iter()
+        .filter_map(|v| split_part(&v.stem).map(|(base, _)| base.to_string()))
+        .collect();
+    if let Some(base) = bases.first()
+        && bases.len() == videos.len()
+        && bases.iter().all(|b| same_name(b, base))
+    {
+        return named_videos(base, videos);
+    }
+
+    videos
+        .into_iter()
+        .max_by_key(|v| std::fs::metadata(&v.path).map(|m| m.len()).unwrap_or(0))
+        .map(|v| vec![v.path])
+        .unwrap_or_default()
+}
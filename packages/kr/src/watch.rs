@@ -0,0 +1,127 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Result;
+use log::warn;
+use notify_debouncer_mini::{
+    DebounceEventResult, Debouncer, new_debouncer,
+    notify::{RecommendedWatcher, RecursiveMode},
+};
+
+use crate::{db::LibraryRoot, discovery::IGNORE_FILE};
+
+// copying a movie folder is a burst of events, report it once it settled
+const DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// Watches library roots with inotify (or the platform's equivalent) and
+/// reports changed paths once a burst of events has settled.
+///
+/// The callback runs on the watcher thread, hand the paths over to the thread
+/// that owns the database and scan them with [`BackgroundScan::changed`], or
+/// with [`Database::apply_changes`] right there.
+///
+/// [`BackgroundScan::changed`]: crate::db::BackgroundScan::changed
+/// [`Database::apply_changes`]: crate::db::Database::apply_changes
+pub struct LibraryWatcher {
+    debouncer: Debouncer<RecommendedWatcher>,
+    watched: Vec<PathBuf>,
+}
+
+impl LibraryWatcher {
+    /// `video_extensions` are the extensions of the library, see
+    /// [`Database::video_extensions`](crate::db::Database::video_extensions).
+    pub fn new(video_extensions: &[String], on_change: impl Fn(Vec<PathBuf>) + Send + 'static) -> Result<Self> {
+        let video_extensions = video_extensions.to_vec();
+        let debouncer = new_debouncer(DEBOUNCE, move |result: DebounceEventResult| {
+            let events = match result {
+                Ok(events) => events,
+                Err(e) => {
+                    warn!("library watch failed: {e}");
+                    return;
+                }
+            };
+
+            let paths: Vec<PathBuf> = events
+                .into_iter()
+                .map(|event| event.path)
+                .filter(|path| is_relevant(path, &video_extensions))
+                .collect();
+
+            if !paths.is_empty() {
+                on_change(paths);
+            }
+        })?;
+
+        Ok(Self { debouncer, watched: vec![] })
+    }
+
+    /// Starts watching `root` and everything below it.
+    pub fn watch(&mut self, root: &Path) -> Result<()> {
+        if self.watched.iter().any(|p| p == root) {
+            return Ok(());
+        }
+
+        self.debouncer.watcher().watch(root, RecursiveMode::Recursive)?;
+        self.watched.push(root.to_owned());
+        Ok(())
+    }
+
+    pub fn unwatch(&mut self, root: &Path) -> Result<()> {
+        self.watched.retain(|p| p != root);
+        self.debouncer.watcher().unwatch(root)?;
+        Ok(())
+    }
+
+    /// Watches the enabled `roots` and stops watching every other path, call
+    /// it again whenever roots are added, removed, enabled or disabled. Roots
+    /// that can't be watched, e.g. an unmounted drive, are retried next time.
+    pub fn sync(&mut self, roots: &[LibraryRoot]) {
+        let stale: Vec<PathBuf> = self
+            .watched
+            .iter()
+            .filter(|p| !roots.iter().any(|r| r.enabled && &r.path == *p))
+            .cloned()
+            .collect();
+
+        for path in stale {
+            if let Err(e) = self.unwatch(&path) {
+                warn!("{path:?} unwatch failed: {e}");
+            }
+        }
+
+        for root in roots.iter().filter(|r| r.enabled) {
+            if let Err(e) = self.watch(&root.path) {
+                warn!("{:?} watch failed: {e}", root.path);
+            }
+        }
+    }
+
+    /// The paths currently watched.
+    pub fn watched(&self) -> &[PathBuf] {
+        &self.watched
+    }
+}
+
+impl std::fmt::Debug for LibraryWatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LibraryWatcher").finish_non_exhaustive()
+    }
+}
+
+/// Nfo files, videos, ignore files and folders can change the library, images
+/// can't. Deleted paths can't be told apart and are always kept.
+fn is_relevant(path: &Path, video_extensions: &[String]) -> bool {
+    let is_nfo = path.extension().is_some_and(|ext| ext == "nfo");
+    let is_video = path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| {
+        video_extensions
+            .iter()
+            .any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(ext))
+    });
+    if is_nfo || is_video || path.file_name().is_some_and(|name| name == IGNORE_FILE) {
+        return true;
+    }
+
+    std::fs::symlink_metadata(path).map(|m| m.is_dir()).unwrap_or(true)
+}
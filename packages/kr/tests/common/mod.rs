@@ -0,0 +1,92 @@
+//! Temp libraries shared by the integration tests.
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+
+use kr::db::{Database, IndexedMovieData, JsonStorage, MovieId};
+
+/// A fresh temp dir for the test `name`, prefixed with the test file it's used in.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "kr-test-{}-{name}-{}",
+        env!("CARGO_CRATE_NAME"),
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+pub fn write(path: &Path, content: impl AsRef<[u8]>) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, content).unwrap();
+}
+
+/// Writes a movie nfo with nothing but a title.
+pub fn write_movie(path: &Path, title: &str) {
+    write(path, format!("<movie><title>{title}</title></movie>"));
+}
+
+/// Creates `files` below `dir`. An nfo is a movie titled after its file name,
+/// any other file holds a single byte.
+pub fn tree(dir: &Path, files: &[&str]) {
+    for file in files {
+        let path = dir.join(file);
+        if path.extension().is_some_and(|ext| ext == "nfo") {
+            write_movie(&path, &path.file_stem().unwrap().to_string_lossy());
+        } else {
+            write(&path, [0]);
+        }
+    }
+}
+
+/// Writes `files`, pairs of a path below `dir` and the content of the file.
+pub fn write_files<C: AsRef<[u8]>>(dir: &Path, files: &[(&str, C)]) {
+    for (path, content) in files {
+        write(&dir.join(path), content);
+    }
+}
+
+/// Opens the library stored in `dir` and scans `root` into it.
+pub fn open(dir: &Path, root: &Path) -> Database {
+    let mut db = Database::with_storage(Box::new(JsonStorage::new(dir.join("kr.json"))));
+    db.load_config(&[root.to_path_buf()]).unwrap();
+    db
+}
+
+/// A library rooted at `<dir>/library` holding `files`, see [`tree`].
+/// Returns the temp dir, the root and the scanned library.
+pub fn library(name: &str, files: &[&str]) -> (PathBuf, PathBuf, Database) {
+    let dir = temp_dir(name);
+    let root = dir.join("library");
+    tree(&root, files);
+
+    let db = open(&dir, &root);
+    (dir, root, db)
+}
+
+/// A library rooted at `<dir>/library` holding `files`, see [`write_files`].
+/// Returns the temp dir, the root and the scanned library.
+pub fn library_with<C: AsRef<[u8]>>(name: &str, files: &[(&str, C)]) -> (PathBuf, PathBuf, Database) {
+    let dir = temp_dir(name);
+    let root = dir.join("library");
+    write_files(&root, files);
+
+    let db = open(&dir, &root);
+    (dir, root, db)
+}
+
+/// Id of the best search result for `title`.
+pub fn id(db: &mut Database, title: &str) -> MovieId {
+    db.search(title).next().unwrap().id.clone()
+}
+
+pub fn titles<'a>(iter: impl Iterator<Item = IndexedMovieData<'a>>) -> Vec<String> {
+    iter.map(|item| item.movie.movie.title.clone()).collect()
+}
+
+/// Titles of the whole library in alphabetical order.
+pub fn all_titles(db: &mut Database) -> Vec<String> {
+    let mut titles = titles(db.order_by_added_time());
+    titles.sort();
+    titles
+}
@@ -0,0 +1,196 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use ahash::AHashSet;
+use common::{all_titles, library};
+use kr::{
+    discovery::{Discovery, Strategy},
+    util::{DirStates, NfoKind, find_new_nfo},
+};
+
+mod common;
+
+/// Creates `files` below a fresh temp dir, see [`common::tree`].
+fn tree(name: &str, files: &[&str]) -> PathBuf {
+    let dir = common::temp_dir(name);
+    common::tree(&dir, files);
+    dir
+}
+
+/// Found files relative to `root`, sorted, with `show`/`episode` marking the kind.
+fn find(root: &Path, discovery: &Discovery) -> Vec<String> {
+    let mut found: Vec<String> = find_new_nfo(root, discovery, &mut DirStates::new(), &AHashSet::new())
+        .unwrap()
+        .into_iter()
+        .map(|nfo| {
+            let path = nfo.path.strip_prefix(root).unwrap().to_string_lossy().replace('\\', "/");
+            match nfo.kind {
+                NfoKind::Movie => path,
+                NfoKind::Show => format!("show {path}"),
+                NfoKind::Episode(_) => format!("episode {path}"),
+            }
+        })
+        .collect();
+    found.sort();
+    found
+}
+
+const LAYOUT: &[&str] = &[
+    "Avatar (2009)/Avatar (2009).nfo",
+    "Heat/movie.nfo",
+    "Flat/Alien.nfo",
+    "Flat/Aliens.nfo",
+    "loose.nfo",
+    "Show/tvshow.nfo",
+    "Show/Season 1/season.nfo",
+    "Show/Season 1/S01E01.nfo",
+];
+
+#[test]
+fn kodi_folder() {
+    let root = tree("kodi", LAYOUT);
+
+    assert_eq!(
+        find(&root, &Discovery::default()),
+        vec![
+            "Avatar (2009)/Avatar (2009).nfo",
+            "episode Show/Season 1/S01E01.nfo",
+            "show Show/tvshow.nfo",
+        ]
+    );
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn movie_nfo() {
+    let root = tree("movie-nfo", LAYOUT);
+
+    assert_eq!(
+        find(&root, &Discovery::new(Strategy::MovieNfo)),
+        vec![
+            "Heat/movie.nfo",
+            "episode Show/Season 1/S01E01.nfo",
+            "show Show/tvshow.nfo",
+        ]
+    );
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn flat() {
+    let root = tree("flat", LAYOUT);
+
+    assert_eq!(
+        find(&root, &Discovery::new(Strategy::Flat)),
+        vec![
+            "Avatar (2009)/Avatar (2009).nfo",
+            "Flat/Alien.nfo",
+            "Flat/Aliens.nfo",
+            "Heat/movie.nfo",
+            "episode Show/Season 1/S01E01.nfo",
+            "loose.nfo",
+            "show Show/tvshow.nfo",
+        ]
+    );
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn include_and_exclude() {
+    let root = tree(
+        "globs",
+        &[
+            "Movies/A.nfo",
+            "Movies/A.sample.nfo",
+            "Movies/extras/B.nfo",
+            "Old/C.nfo",
+            "Other/Old/D.nfo",
+            "Other/E.nfo",
+        ],
+    );
+
+    let discovery = Discovery {
+        strategy: Strategy::Flat,
+        include: vec![],
+        exclude: vec!["extras/".to_string(), "*.sample.nfo".to_string(), "/Old".to_string()],
+    };
+    assert_eq!(find(&root, &discovery), vec!["Movies/A.nfo", "Other/E.nfo", "Other/Old/D.nfo"]);
+
+    let discovery = Discovery {
+        include: vec!["Movies/*.nfo".to_string(), "D.nfo".to_string()],
+        ..discovery
+    };
+    assert_eq!(find(&root, &discovery), vec!["Movies/A.nfo", "Other/Old/D.nfo"]);
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn ignore_files() {
+    let root = tree("kkignore", &["A.nfo", "B/B.nfo", "Sub/B/B.nfo", "Sub/C/C.nfo", "trailer/T.nfo"]);
+    std::fs::write(root.join(".kkignore"), "# trailers are not movies\n\ntrailer\n").unwrap();
+    // only applies below Sub
+    std::fs::write(root.join("Sub/.kkignore"), "B\n").unwrap();
+
+    assert_eq!(
+        find(&root, &Discovery::new(Strategy::Flat)),
+        vec!["A.nfo", "B/B.nfo", "Sub/C/C.nfo"]
+    );
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn invalid_glob() {
+    let root = tree("invalid", &["A/A.nfo"]);
+    let discovery = Discovery {
+        exclude: vec!["a[".to_string()],
+        ..Default::default()
+    };
+
+    let error = find_new_nfo(&root, &discovery, &mut DirStates::new(), &AHashSet::new()).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn change_strategy_of_root() {
+    let (dir, root, mut db) = library("switch", &["Avatar/Avatar.nfo", "Heat/movie.nfo"]);
+    assert_eq!(all_titles(&mut db), vec!["Avatar"]);
+
+    db.set_root_discovery(&root, Discovery::new(Strategy::MovieNfo)).unwrap();
+    assert_eq!(all_titles(&mut db), vec!["movie"]);
+    assert_eq!(db.roots()[0].discovery.strategy, Strategy::MovieNfo);
+
+    db.set_root_discovery(&root, Discovery::new(Strategy::Flat)).unwrap();
+    assert_eq!(all_titles(&mut db), vec!["Avatar", "movie"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn newly_ignored_entries_are_removed() {
+    let files = &["Avatar/Avatar.nfo", "Heat/Heat.nfo", "Trailer/Trailer.nfo", "Extras/Show/tvshow.nfo"];
+    let (dir, root, mut db) = library("pruned", files);
+    assert_eq!(all_titles(&mut db), vec!["Avatar", "Heat", "Trailer"]);
+    assert_eq!(db.shows().len(), 1);
+
+    std::fs::write(root.join(".kkignore"), "Trailer\n").unwrap();
+    db.reload().unwrap();
+    assert_eq!(all_titles(&mut db), vec!["Avatar", "Heat"]);
+    assert_eq!(db.scan_report().removed, 1);
+
+    // the globs changed in the stored config, not through the library
+    let path = dir.join("kr.json");
+    let mut stored: serde_json::Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    stored["roots"][0]["discovery"]["exclude"] = serde_json::json!(["Heat", "Extras"]);
+    std::fs::write(&path, stored.to_string()).unwrap();
+    db.reload().unwrap();
+    assert_eq!(all_titles(&mut db), vec!["Avatar"]);
+    assert!(db.shows().is_empty());
+
+    // picked up again once the rules allow it
+    std::fs::remove_file(root.join(".kkignore")).unwrap();
+    db.reload().unwrap();
+    assert_eq!(all_titles(&mut db), vec!["Avatar", "Trailer"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,124 @@
+use common::{library_with, titles, write};
+use kr::db::{Database, Filter};
+
+mod common;
+
+const LIBRARY: &[(&str, &str)] = &[
+    (
+        "Heat/Heat.nfo",
+        "<movie><title>Heat</title><year>1995</year>\
+         <actor><name>Al Pacino</name></actor><actor><name>Robert De Niro</name></actor>\
+         <genre>Crime</genre><genre>Thriller</genre><tag>heist</tag>\
+         <ratings><rating name=\"imdb\" default=\"true\"><value>8.3</value></rating></ratings></movie>",
+    ),
+    (
+        "Godfather/Godfather.nfo",
+        "<movie><title>The Godfather</title><premiered>1972-03-24</premiered>\
+         <actor><name>Al Pacino</name></actor><genre>Crime</genre><genre>Drama</genre>\
+         <tag>mafia</tag><rating>9.2</rating></movie>",
+    ),
+    (
+        "Ronin/Ronin.nfo",
+        "<movie><title>Ronin</title><year>1998</year><actor><name>Robert De Niro</name></actor>\
+         <genre>Action</genre><genre>Thriller</genre><tag>heist</tag></movie>",
+    ),
+    (
+        "Alien/Alien.nfo",
+        "<movie><title>Alien</title><year>1979</year><genre>Horror</genre><label>Fox</label><rating>8.5</rating></movie>",
+    ),
+];
+
+fn filtered(db: &mut Database, filter: &Filter) -> Vec<String> {
+    let mut titles = titles(db.filter(filter));
+    titles.sort();
+    titles
+}
+
+fn names(counts: &[(String, usize)]) -> Vec<(&str, usize)> {
+    counts.iter().map(|(name, count)| (name.as_str(), *count)).collect()
+}
+
+#[test]
+fn filters_compose() {
+    let (dir, _, mut db) = library_with("compose", LIBRARY);
+
+    let crime = Filter::actor("Al Pacino").and(Filter::genre("Crime"));
+    assert_eq!(filtered(&mut db, &crime), vec!["Heat", "The Godfather"]);
+
+    // narrowed from the cached result of `crime`
+    let filter = crime.clone().and(Filter::tag("mafia").not());
+    assert_eq!(filter.parent(), Some(crime));
+    assert_eq!(filtered(&mut db, &filter), vec!["Heat"]);
+
+    let filter = Filter::tag("mafia").or(Filter::label("Fox"));
+    assert_eq!(filtered(&mut db, &filter), vec!["Alien", "The Godfather"]);
+
+    let filter = Filter::All.and(Filter::genre("Thriller")).and(Filter::actor("Al Pacino").not());
+    assert_eq!(filtered(&mut db, &filter), vec!["Ronin"]);
+    assert!(filtered(&mut db, &Filter::genre("Western")).is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn facet_counts() {
+    let (dir, _, mut db) = library_with("facets", LIBRARY);
+
+    let facets = db.facets(&Filter::genre("Thriller")).clone();
+    assert_eq!(names(&facets.actor), vec![("Robert De Niro", 2), ("Al Pacino", 1)]);
+    assert_eq!(names(&facets.genre), vec![("Thriller", 2), ("Action", 1), ("Crime", 1)]);
+    assert_eq!(names(&facets.tag), vec![("heist", 2)]);
+    assert!(facets.label.is_empty());
+    assert_eq!(facets.year, vec![(1995, 1), (1998, 1)]);
+
+    let facets = db.facets(&Filter::All);
+    assert_eq!(facets.genre[0], ("Crime".to_string(), 2));
+    assert_eq!(facets.year.len(), 4);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn year_bounds() {
+    let (dir, _, mut db) = library_with("years", LIBRARY);
+
+    assert_eq!(filtered(&mut db, &Filter::year(1972)), vec!["The Godfather"]);
+    // both ends are included
+    assert_eq!(filtered(&mut db, &Filter::years(1979..=1995)), vec!["Alien", "Heat"]);
+    assert_eq!(filtered(&mut db, &Filter::years(1990..=2000)), vec!["Heat", "Ronin"]);
+    assert!(filtered(&mut db, &Filter::years(2000..=2020)).is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rating_bounds() {
+    let (dir, _, mut db) = library_with("ratings", LIBRARY);
+
+    assert_eq!(filtered(&mut db, &Filter::rating(8.5..=10.)), vec!["Alien", "The Godfather"]);
+    // a movie without rating is in no range
+    assert_eq!(filtered(&mut db, &Filter::rating(0. ..=8.4)), vec!["Heat"]);
+    assert_eq!(filtered(&mut db, &Filter::rating(8.3..=8.3)), vec!["Heat"]);
+
+    let filter = Filter::rating(8.0..=10.).and(Filter::years(1970..=1990));
+    assert_eq!(filtered(&mut db, &filter), vec!["Alien", "The Godfather"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn newest_first() {
+    let (dir, root, mut db) = library_with("newest", LIBRARY);
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    write(
+        &root.join("Collateral/Collateral.nfo"),
+        "<movie><title>Collateral</title><year>2004</year><genre>Thriller</genre></movie>",
+    );
+    db.reload().unwrap();
+    assert_eq!(titles(db.filter(&Filter::genre("Thriller")))[0], "Collateral");
+
+    // a moved entry comes last in the library, that doesn't make it new
+    std::fs::create_dir_all(root.join("Crime")).unwrap();
+    std::fs::rename(root.join("Heat"), root.join("Crime/Heat")).unwrap();
+    db.reload().unwrap();
+    let found = titles(db.filter(&Filter::genre("Thriller")));
+    assert_eq!(found.len(), 3);
+    assert_eq!(found[0], "Collateral");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
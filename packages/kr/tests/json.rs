@@ -0,0 +1,82 @@
+use std::{
+    fs::File,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use common::{id, library};
+use kr::db::{JsonStorage, Storage};
+
+mod common;
+
+const LIBRARY: &[&str] = &["Alien/Alien.nfo", "Heat/Heat.nfo"];
+
+/// Contents of the files set aside as unreadable.
+fn unreadable(dir: &Path) -> Vec<String> {
+    std::fs::read_dir(dir)
+        .unwrap()
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("kr.json.unreadable-"))
+        .map(|entry| std::fs::read_to_string(entry.path()).unwrap())
+        .collect()
+}
+
+#[test]
+fn corrupt_file_is_restored_from_backup() {
+    let (dir, _, mut db) = library("restore", LIBRARY);
+    let path = dir.join("kr.json");
+    let heat = id(&mut db, "heat");
+
+    // the library before this change becomes kr.json.1
+    db.set_fav(&heat, true).unwrap();
+    assert!(dir.join("kr.json.1").exists());
+
+    std::fs::write(&path, "{\"version\": 7, \"movies\": [").unwrap();
+    let config = JsonStorage::new(path.clone()).load().unwrap().unwrap();
+    assert_eq!(config.movies.len(), 2);
+    assert!(config.movies.iter().all(|d| !d.fav));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn corrupt_file_is_not_kept_as_backup() {
+    let (dir, _, mut db) = library("no-corrupt-backup", LIBRARY);
+    let path = dir.join("kr.json");
+    let heat = id(&mut db, "heat");
+    db.set_fav(&heat, true).unwrap();
+
+    // the backup is old enough to be replaced, but not by a broken file
+    let old = SystemTime::now() - Duration::from_secs(24 * 60 * 60);
+    File::options().write(true).open(dir.join("kr.json.1")).unwrap().set_modified(old).unwrap();
+    std::fs::write(&path, "not json").unwrap();
+    db.set_fav(&heat, false).unwrap();
+
+    assert!(!dir.join("kr.json.2").exists());
+    let storage = JsonStorage::new(dir.join("kr.json.1"));
+    assert_eq!(storage.load().unwrap().unwrap().movies.len(), 2);
+    assert_eq!(unreadable(&dir), vec!["not json"]);
+
+    // the main file is good again
+    let config = JsonStorage::new(path).load().unwrap().unwrap();
+    assert!(config.movies.iter().all(|d| !d.fav));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unreadable_file_is_set_aside() {
+    let (dir, _, mut db) = library("set-aside", LIBRARY);
+    let path = dir.join("kr.json");
+    let heat = id(&mut db, "heat");
+    db.set_fav(&heat, true).unwrap();
+    std::fs::write(&path, "{\"version\": 7, \"movies\": [").unwrap();
+
+    // restored from the backup, the broken file stays until the next write
+    let storage = JsonStorage::new(path.clone());
+    let config = storage.load().unwrap().unwrap();
+    assert!(unreadable(&dir).is_empty());
+
+    storage.flush(&config).unwrap();
+    assert_eq!(unreadable(&dir), vec!["{\"version\": 7, \"movies\": ["]);
+    assert_eq!(storage.load().unwrap().unwrap().movies.len(), 2);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
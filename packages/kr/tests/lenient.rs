@@ -0,0 +1,87 @@
+use std::path::PathBuf;
+
+use common::{open, temp_dir, write};
+use kr::{Movie, nfo};
+
+mod common;
+
+fn fixture_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/lenient")
+        .join(name)
+}
+
+fn fixture(name: &str) -> Movie {
+    Movie::load(&fixture_path(name)).unwrap()
+}
+
+#[test]
+fn byte_order_marks() {
+    assert_eq!(fixture("utf8_bom.nfo").title, "Amélie");
+    assert_eq!(fixture("utf16_bom.nfo").title, "Amélie");
+}
+
+#[test]
+fn declared_encoding() {
+    let movie = fixture("shift_jis.nfo");
+    assert_eq!(movie.title, "東京物語");
+    assert!(movie.plot.unwrap().starts_with("東京の下町"));
+}
+
+#[test]
+fn detected_encoding() {
+    // declares UTF-8 but is GBK
+    let movie = fixture("undeclared_gbk.nfo");
+    assert_eq!(movie.title, "霸王别姬");
+    assert!(movie.plot.unwrap().contains("京剧"));
+}
+
+#[test]
+fn html_in_text() {
+    let movie = fixture("html.nfo");
+    assert_eq!(movie.title, "Tom & Jerry\u{a0}…");
+    assert_eq!(movie.plot.as_deref(), Some("Cat & mouse.\nAgain — and again.\n© ©"));
+    assert_eq!(movie.outline.as_deref(), Some("<b>bold</b> & kept"));
+}
+
+#[test]
+fn missing_fields() {
+    let movie = fixture("missing_fields.nfo");
+    assert_eq!(movie.title, "missing_fields");
+    assert_eq!(movie.year, Some(1999));
+    assert_eq!(movie.actor.len(), 1);
+    assert_eq!(movie.actor[0].name, "Someone");
+}
+
+#[test]
+fn sanitize_keeps_valid_xml() {
+    let xml = "<movie><title>A &amp; B &#38; &#x26; &lt;C&gt;</title></movie>";
+    assert!(matches!(nfo::sanitize(xml), std::borrow::Cow::Owned(ref s) if s == xml));
+    assert!(matches!(nfo::sanitize("<movie/>"), std::borrow::Cow::Borrowed(_)));
+}
+
+#[test]
+fn failures_are_reported() {
+    let dir = temp_dir("report");
+    let root = dir.join("library");
+
+    for name in ["shift_jis", "html", "broken"] {
+        let content = std::fs::read(fixture_path(&format!("{name}.nfo"))).unwrap();
+        write(&root.join(format!("{name}/{name}.nfo")), &content);
+    }
+
+    let mut db = open(&dir, &root);
+
+    assert_eq!(db.order_by_added_time().count(), 2);
+    let failures = &db.scan_report().failures;
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].path, root.join("broken/broken.nfo"));
+    assert!(!failures[0].error.is_empty());
+
+    // the report only covers the latest scan
+    std::fs::remove_dir_all(root.join("broken")).unwrap();
+    db.reload().unwrap();
+    assert!(!db.scan_report().has_failures());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
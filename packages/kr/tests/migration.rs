@@ -0,0 +1,240 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use kr::{
+    db::{Database, JsonStorage, NewerVersion, SCHEMA_VERSION, migrate},
+    discovery::{Discovery, Strategy},
+    video,
+};
+use serde_json::{Value, json};
+
+mod common;
+
+fn fixture_path(version: u32) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/config")
+        .join(format!("v{version}.json"))
+}
+
+fn fixture(version: u32) -> Value {
+    let content = std::fs::read_to_string(fixture_path(version)).unwrap();
+    serde_json::from_str(&content).unwrap()
+}
+
+fn time(secs: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+#[test]
+fn every_version_has_a_fixture() {
+    for version in 0..=SCHEMA_VERSION {
+        assert!(fixture_path(version).exists(), "missing fixture for schema version {version}");
+    }
+}
+
+#[test]
+fn every_fixture_migrates_to_current_version() {
+    for version in 0..=SCHEMA_VERSION {
+        let config = migrate(fixture(version)).unwrap();
+        assert_eq!(config.version, SCHEMA_VERSION);
+
+        let first = &config.movies[0];
+        assert_eq!(first.path, PathBuf::from("/media/movies/ABC-123/ABC-123.nfo"));
+        assert_eq!(first.movie.num.as_deref(), Some("ABC-123"));
+        assert_eq!(first.added_time, time(1_700_000_000));
+        assert!(first.fav);
+    }
+}
+
+#[test]
+fn migrate_v0() {
+    let config = migrate(fixture(0)).unwrap();
+
+    assert_eq!(config.movies.len(), 2);
+    assert!(config.roots.is_empty());
+
+    let second = &config.movies[1];
+    assert_eq!(second.movie.title, "Second Movie");
+    assert_eq!(second.added_time, time(1_700_000_100) + Duration::from_nanos(500));
+    assert!(!second.fav);
+    assert!(second.markers.is_empty());
+    assert_eq!(second.resume_pos, None);
+    assert_eq!(second.last_played, None);
+    assert_eq!(second.play_count, 0);
+    assert!(!second.watched);
+    assert_eq!(second.nfo_mtime, None);
+}
+
+#[test]
+fn migrate_v1() {
+    let config = migrate(fixture(1)).unwrap();
+
+    assert_eq!(config.roots.len(), 1);
+    assert_eq!(config.roots[0].path, PathBuf::from("/media/movies"));
+    assert_eq!(config.roots[0].last_scan_time, time(1_700_000_200));
+    assert!(config.roots[0].enabled);
+
+    let first = &config.movies[0];
+    assert_eq!(first.root, PathBuf::from("/media/movies"));
+    assert_eq!(first.markers, vec![12.5, 300.0]);
+    assert_eq!(first.resume_pos, Some(120.0));
+    assert_eq!(first.last_played, Some(time(1_700_001_000)));
+    assert_eq!(first.play_count, 3);
+    assert!(first.watched);
+    assert_eq!(first.movie.thumb_path(), Some("ABC-123-thumb.jpg"));
+    assert!(first.movie.fanart.is_none());
+}
+
+#[test]
+fn migrate_v2() {
+    let config = migrate(fixture(2)).unwrap();
+
+    let movie = &config.movies[0].movie;
+    assert_eq!(movie.thumb_path(), Some("ABC-123-thumb.jpg"));
+    assert_eq!(movie.fanart.as_ref().unwrap().thumb[0].path, "ABC-123-fanart.jpg");
+    assert_eq!(movie.year, Some(2020));
+    assert_eq!(movie.runtime, Some(120));
+    assert_eq!(movie.default_rating(), Some(7.5));
+    assert_eq!(movie.set.as_ref().and_then(|s| s.name()), Some("Collection"));
+    assert_eq!(movie.uniqueid.as_ref().unwrap()[0].value, "tt0000001");
+    assert_eq!(movie.actor[0].order, Some(0));
+    assert!(config.shows.is_empty());
+    assert_eq!(config.movies[0].show, None);
+}
+
+#[test]
+fn migrate_v3() {
+    let config = migrate(fixture(3)).unwrap();
+
+    assert_eq!(config.shows.len(), 1);
+    let show = &config.shows[0];
+    assert_eq!(show.show.title, "Some Show");
+    assert_eq!(show.show.thumb_path(), Some("landscape.jpg"));
+    assert_eq!(show.show.season_thumb_path(1), Some("season01-poster.jpg"));
+    assert_eq!(show.show.season_name(1), Some("The Beginning"));
+
+    let episode = &config.movies[1];
+    assert!(episode.is_episode());
+    assert_eq!(episode.show.as_ref(), Some(&show.path));
+    assert_eq!(episode.episode_number(), Some((1, 2)));
+    assert_eq!(episode.movie.showtitle.as_deref(), Some("Some Show"));
+    assert!(config.roots.iter().all(|r| r.discovery == Discovery::default()));
+}
+
+#[test]
+fn migrate_v4() {
+    let config = migrate(fixture(4)).unwrap();
+
+    assert_eq!(config.roots[0].discovery.strategy, Strategy::KodiFolder);
+    let shows = &config.roots[1].discovery;
+    assert_eq!(shows.strategy, Strategy::Flat);
+    assert!(shows.include.is_empty());
+    assert_eq!(shows.exclude, vec!["extras", "*.sample.nfo"]);
+    assert!(config.roots.iter().all(|r| r.dirs.is_empty()));
+}
+
+#[test]
+fn migrate_v5() {
+    let config = migrate(fixture(5)).unwrap();
+
+    let dirs = &config.roots[0].dirs;
+    assert_eq!(dirs.len(), 2);
+    let root = &dirs[Path::new("")];
+    assert_eq!(root.mtime, Some(time(1_700_000_150)));
+    assert_eq!(root.subdirs, vec!["ABC-123"]);
+    assert_eq!(dirs[Path::new("ABC-123")].nfos, vec!["ABC-123.nfo"]);
+    assert!(config.roots[1].dirs.is_empty());
+
+    // the default uniqueid, an episode without any gets a random id
+    assert_eq!(config.movies[0].id.as_str(), "imdb:tt0000001");
+    assert_ne!(config.movies[1].id, config.movies[0].id);
+    assert!(!config.movies[1].id.as_str().is_empty());
+}
+
+#[test]
+fn migrate_v6() {
+    let config = migrate(fixture(6)).unwrap();
+
+    assert_eq!(config.movies[0].id.as_str(), "imdb:tt0000001");
+    assert_eq!(config.movies[1].id.as_str(), "7f3c2a9e-4b1d-4e8a-9c6f-2d5e8b1a0c34");
+
+    // videos are looked up by the next scan
+    assert_eq!(config.video_extensions, video::DEFAULT_EXTENSIONS);
+    assert!(config.movies.iter().all(|m| m.videos.is_empty()));
+}
+
+#[test]
+fn migrate_v7() {
+    let config = migrate(fixture(7)).unwrap();
+
+    assert_eq!(config.video_extensions, vec!["mkv", "mp4"]);
+    assert_eq!(
+        config.movies[0].videos,
+        vec![
+            PathBuf::from("/media/movies/ABC-123/ABC-123-cd1.mkv"),
+            PathBuf::from("/media/movies/ABC-123/ABC-123-cd2.mkv"),
+        ]
+    );
+    assert!(config.movies[1].videos.is_empty());
+}
+
+#[test]
+fn duplicate_ids_are_not_migrated() {
+    let mut value = fixture(5);
+    let copy = value["movies"][0].clone();
+    value["movies"].as_array_mut().unwrap().push(copy);
+
+    let config = migrate(value).unwrap();
+    assert_eq!(config.movies[0].id.as_str(), "imdb:tt0000001");
+    assert_ne!(config.movies[2].id, config.movies[0].id);
+}
+
+#[test]
+fn current_version_roundtrips() {
+    let config = migrate(fixture(SCHEMA_VERSION)).unwrap();
+    let value = serde_json::to_value(&config).unwrap();
+
+    let again = migrate(value.clone()).unwrap();
+    assert_eq!(serde_json::to_value(&again).unwrap(), value);
+}
+
+#[test]
+fn newer_version_is_rejected() {
+    let mut value = fixture(SCHEMA_VERSION);
+    value["version"] = json!(SCHEMA_VERSION + 1);
+
+    let error = migrate(value).unwrap_err();
+    assert!(error.is::<NewerVersion>());
+}
+
+#[test]
+fn newer_library_is_left_untouched() {
+    let dir = common::temp_dir("newer");
+    let root = dir.join("library");
+    common::write_movie(&root.join("Heat/Heat.nfo"), "Heat");
+
+    let mut value = fixture(SCHEMA_VERSION);
+    value["version"] = json!(SCHEMA_VERSION + 1);
+    let path = dir.join("kr.json");
+    let content = value.to_string();
+    common::write(&path, &content);
+    // an older backup would drop whatever the newer version added
+    common::write(&dir.join("kr.json.1"), fixture(SCHEMA_VERSION).to_string());
+
+    let mut db = Database::with_storage(Box::new(JsonStorage::new(path.clone())));
+    let error = db.load_cached(std::slice::from_ref(&root)).unwrap_err();
+    assert!(error.is::<NewerVersion>());
+
+    let result = db.background_scan().run(|_| {});
+    assert!(db.finish_scan(result).is_err());
+    assert!(db.load_config(std::slice::from_ref(&root)).is_err());
+    assert!(db.reload().is_err());
+
+    assert_eq!(std::fs::read(&path).unwrap(), content.as_bytes());
+    let mut files: Vec<_> = std::fs::read_dir(&dir).unwrap().flatten().map(|e| e.file_name()).collect();
+    files.sort();
+    assert_eq!(files, ["kr.json", "kr.json.1", "library"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
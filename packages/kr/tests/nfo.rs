@@ -0,0 +1,195 @@
+use std::path::PathBuf;
+
+use kr::{Movie, db::release_date};
+
+fn fixture(name: &str) -> Movie {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/nfo")
+        .join(name);
+    let content = std::fs::read_to_string(path).unwrap();
+    Movie::from_nfo(&content).unwrap()
+}
+
+#[test]
+fn every_fixture_parses() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/nfo");
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(Movie::from_nfo(&content).is_ok(), "{path:?} failed to parse");
+    }
+}
+
+#[test]
+fn kodi() {
+    let movie = fixture("kodi.nfo");
+
+    assert_eq!(movie.title, "Avatar");
+    assert_eq!(movie.sorttitle.as_deref(), Some("Avatar 1"));
+    assert_eq!(movie.year, Some(2009));
+    assert_eq!(movie.premiered.as_deref(), Some("2009-12-10"));
+    assert_eq!(release_date(&movie), Some((2009, 12, 10)));
+    assert_eq!(movie.runtime, Some(162));
+    assert_eq!(movie.mpaa.as_deref(), Some("Rated PG-13"));
+    assert_eq!(movie.userrating, Some(0.));
+    assert_eq!(movie.top250, Some(0));
+
+    let ratings = &movie.ratings.as_ref().unwrap().rating;
+    assert_eq!(ratings.len(), 2);
+    assert_eq!(ratings[0].name.as_deref(), Some("imdb"));
+    assert_eq!(ratings[0].max, Some(10.));
+    assert_eq!(ratings[0].default, Some(true));
+    assert_eq!(ratings[0].votes, Some(1_200_000));
+    assert_eq!(ratings[1].default, None);
+    assert_eq!(movie.default_rating(), Some(7.9));
+
+    assert_eq!(movie.thumb.len(), 3);
+    assert_eq!(movie.thumb[0].aspect.as_deref(), Some("poster"));
+    assert_eq!(
+        movie.thumb_path(),
+        Some("https://assets.fanart.tv/fanart/movies/19995/moviethumb/avatar.jpg")
+    );
+    let fanart = movie.fanart.as_ref().unwrap();
+    assert_eq!(fanart.thumb.len(), 2);
+    assert_eq!(
+        fanart.thumb[0].preview.as_deref(),
+        Some("https://image.tmdb.org/t/p/w780/backdrop1.jpg")
+    );
+
+    let ids = movie.uniqueid.as_ref().unwrap();
+    assert_eq!(ids.len(), 2);
+    assert_eq!(ids[0].kind.as_deref(), Some("imdb"));
+    assert_eq!(ids[0].default, Some(true));
+    assert_eq!(ids[0].value, "tt0499549");
+    assert_eq!(ids[1].value, "19995");
+    assert_eq!(movie.id.as_deref(), Some("tt0499549"));
+
+    assert_eq!(movie.set.as_ref().and_then(|s| s.name()), Some("Avatar Collection"));
+    assert_eq!(movie.studio.as_ref().unwrap().len(), 2);
+    assert_eq!(movie.director.as_deref(), Some(&["James Cameron".to_string()][..]));
+    assert_eq!(movie.credits.as_deref(), Some(&["James Cameron".to_string()][..]));
+    assert_eq!(movie.country.as_ref().unwrap().len(), 2);
+    assert!(movie.trailer.as_deref().unwrap().contains("videoid=5PSNL1qE6VY"));
+
+    let streams = movie.fileinfo.as_ref().unwrap().streamdetails.as_ref().unwrap();
+    assert_eq!(streams.video[0].codec.as_deref(), Some("h264"));
+    assert_eq!(streams.video[0].width, Some(1920));
+    assert_eq!(streams.video[0].height, Some(1080));
+    assert_eq!(streams.video[0].durationinseconds, Some(9720));
+    assert_eq!(streams.audio.len(), 2);
+    assert_eq!(streams.audio[1].language.as_deref(), Some("fre"));
+    assert_eq!(streams.audio[0].channels, Some(6));
+    assert_eq!(streams.subtitle[0].language.as_deref(), Some("eng"));
+
+    assert_eq!(movie.actor.len(), 2);
+    assert_eq!(movie.actor[1].name, "Zoe Saldana");
+    assert_eq!(movie.actor[1].role.as_deref(), Some("Neytiri"));
+    assert_eq!(movie.actor[1].order, Some(1));
+}
+
+#[test]
+fn jellyfin() {
+    let movie = fixture("jellyfin.nfo");
+
+    assert_eq!(movie.title, "Avatar: The Way of Water");
+    assert!(movie.plot.as_deref().unwrap().starts_with("Ten years after"));
+    assert!(movie.outline.as_deref().unwrap().starts_with("Jake Sully"));
+    assert_eq!(movie.rating, Some(7.6));
+    assert_eq!(movie.default_rating(), Some(7.6));
+    assert_eq!(movie.year, Some(2022));
+    assert_eq!(movie.runtime, Some(192));
+    assert_eq!(movie.releasedate.as_deref(), Some("2022-12-14"));
+    assert_eq!(movie.set.as_ref().and_then(|s| s.name()), Some("Avatar Collection"));
+    assert!(movie.thumb.is_empty());
+    assert_eq!(movie.thumb_path(), None);
+
+    let ids = movie.uniqueid.as_ref().unwrap();
+    assert_eq!(ids[0].kind.as_deref(), Some("imdb"));
+    assert_eq!(ids[0].default, None);
+
+    let video = &movie.fileinfo.as_ref().unwrap().streamdetails.as_ref().unwrap().video[0];
+    assert_eq!(video.codec.as_deref(), Some("hevc"));
+    // jellyfin writes the aspect ratio as text
+    assert_eq!(video.aspect, None);
+    assert_eq!(video.width, Some(3840));
+    assert_eq!(video.hdrtype.as_deref(), Some("hdr10"));
+
+    assert_eq!(movie.actor.len(), 2);
+    assert_eq!(movie.actor[1].thumb, None);
+}
+
+#[test]
+fn tinymediamanager() {
+    let movie = fixture("tinymediamanager.nfo");
+
+    assert_eq!(movie.title, "Spirited Away");
+    assert_eq!(movie.originaltitle.as_deref(), Some("千と千尋の神隠し"));
+    assert_eq!(movie.top250, Some(31));
+    assert_eq!(movie.userrating, Some(0.));
+    assert_eq!(movie.default_rating(), Some(8.5));
+    assert_eq!(movie.set.as_ref().and_then(|s| s.name()), None);
+    assert_eq!(release_date(&movie), Some((2001, 7, 20)));
+    assert_eq!(movie.director.as_deref(), Some(&["Hayao Miyazaki".to_string()][..]));
+    assert_eq!(movie.tag.as_ref().unwrap().len(), 2);
+    assert_eq!(
+        movie.thumb_path(),
+        Some("https://image.tmdb.org/t/p/original/spirited.jpg")
+    );
+
+    let ids = movie.uniqueid.as_ref().unwrap();
+    assert_eq!(ids[0].default, Some(false));
+    assert_eq!(ids[1].default, Some(true));
+    assert_eq!(ids[1].value, "tt0245429");
+
+    let streams = movie.fileinfo.as_ref().unwrap().streamdetails.as_ref().unwrap();
+    assert_eq!(streams.video[0].aspect, Some(1.85));
+    assert_eq!(streams.audio[0].codec.as_deref(), Some("AAC"));
+}
+
+#[test]
+fn scraper() {
+    let movie = fixture("scraper.nfo");
+
+    assert_eq!(movie.num.as_deref(), Some("ABC-123"));
+    assert_eq!(movie.thumb_path(), Some("ABC-123-thumb.jpg"));
+    assert_eq!(movie.poster.as_deref(), Some("ABC-123-poster.jpg"));
+    assert_eq!(
+        movie.fanart.as_ref().and_then(|f| f.path.as_deref()),
+        Some("ABC-123-fanart.jpg")
+    );
+    assert_eq!(movie.label.as_deref(), Some("Label"));
+    assert_eq!(release_date(&movie), Some((2020, 1, 2)));
+    assert_eq!(movie.year, None);
+    assert_eq!(movie.actor[0].thumb.as_deref(), Some("actor-one.jpg"));
+}
+
+#[test]
+fn malformed_numbers_are_ignored() {
+    let movie = fixture("malformed_numbers.nfo");
+
+    assert_eq!(movie.year, None);
+    assert_eq!(movie.runtime, None);
+    assert_eq!(movie.rating, None);
+    assert_eq!(movie.top250, None);
+    assert_eq!(movie.default_rating(), None);
+
+    let rating = &movie.ratings.as_ref().unwrap().rating[0];
+    assert_eq!(rating.max, None);
+    assert_eq!(rating.default, None);
+    assert_eq!(rating.votes, None);
+    assert_eq!(movie.actor[0].order, None);
+
+    let video = &movie.fileinfo.as_ref().unwrap().streamdetails.as_ref().unwrap().video[0];
+    assert_eq!(video.width, None);
+    assert_eq!(video.height, Some(1080));
+}
+
+#[test]
+fn json_roundtrip() {
+    for name in ["kodi.nfo", "jellyfin.nfo", "tinymediamanager.nfo", "scraper.nfo"] {
+        let movie = fixture(name);
+        let value = serde_json::to_value(&movie).unwrap();
+        let again: Movie = serde_json::from_value(value.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&again).unwrap(), value, "{name}");
+    }
+}
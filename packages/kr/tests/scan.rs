@@ -0,0 +1,453 @@
+use std::{
+    fs::File,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use common::{all_titles, id, library, temp_dir, write, write_movie};
+use kr::{
+    db::{Database, JsonStorage, ScanProgress},
+    discovery::{Discovery, Strategy},
+};
+
+mod common;
+
+/// Sets the mtime of a file or directory into the past.
+fn age(path: &Path) {
+    let old = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    File::open(path).unwrap().set_modified(old).unwrap();
+}
+
+const AVATAR: &[&str] = &["Collection/Avatar/Avatar.nfo"];
+
+#[test]
+fn nested_nfo_is_found() {
+    let (dir, root, mut db) = library("nested", AVATAR);
+
+    // only the direct parent gets a new mtime
+    write_movie(&root.join("Collection/Heat/Heat.nfo"), "Heat");
+    age(&root.join("Collection"));
+    db.reload().unwrap();
+
+    assert_eq!(all_titles(&mut db), vec!["Avatar", "Heat"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn moved_in_folder_with_old_mtime() {
+    let (dir, root, mut db) = library("moved-in", AVATAR);
+
+    let outside = dir.join("Alien");
+    write_movie(&outside.join("Alien.nfo"), "Alien");
+    age(&outside.join("Alien.nfo"));
+    age(&outside);
+    std::fs::rename(&outside, root.join("Collection/Alien")).unwrap();
+    age(&root.join("Collection"));
+    db.reload().unwrap();
+
+    assert_eq!(all_titles(&mut db), vec!["Alien", "Avatar"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn gone_and_changed() {
+    let (dir, root, mut db) = library("gone", AVATAR);
+    write_movie(&root.join("Collection/Heat/Heat.nfo"), "Heat");
+    db.reload().unwrap();
+    assert!(db.roots()[0].dirs.contains_key(Path::new("Collection/Heat")));
+
+    std::fs::remove_dir_all(root.join("Collection/Heat")).unwrap();
+    write_movie(&root.join("Collection/Avatar/Avatar.nfo"), "Avatar: Extended");
+    // pretend the edit happened well after the last scan
+    File::open(root.join("Collection/Avatar/Avatar.nfo"))
+        .unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(60))
+        .unwrap();
+    db.reload().unwrap();
+
+    assert_eq!(all_titles(&mut db), vec!["Avatar: Extended"]);
+    assert!(!db.roots()[0].dirs.contains_key(Path::new("Collection/Heat")));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn full_rescan() {
+    let (dir, root, mut db) = library("full", AVATAR);
+
+    let nfo = root.join("Collection/Avatar/Avatar.nfo");
+    let mtime = std::fs::metadata(&nfo).unwrap().modified().unwrap();
+    write_movie(&nfo, "Avatar 2");
+    File::open(&nfo).unwrap().set_modified(mtime).unwrap();
+
+    db.reload().unwrap();
+    assert_eq!(all_titles(&mut db), vec!["Avatar"]);
+
+    db.full_rescan().unwrap();
+    assert_eq!(all_titles(&mut db), vec!["Avatar 2"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn changed_nfo_keeps_user_data() {
+    let (dir, root, mut db) = library("changed", AVATAR);
+    let avatar = id(&mut db, "avatar");
+    let added_time = db.get_movie(&avatar).unwrap().added_time;
+    db.set_fav(&avatar, true).unwrap();
+
+    let nfo = root.join("Collection/Avatar/Avatar.nfo");
+    write(&nfo, "<movie><title>Avatar</title><tag>3d</tag></movie>");
+    File::open(&nfo)
+        .unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(60))
+        .unwrap();
+    db.reload().unwrap();
+
+    let data = db.get_movie(&avatar).unwrap();
+    assert_eq!(data.movie.tag.as_deref(), Some(&["3d".to_string()][..]));
+    assert!(data.fav);
+    assert_eq!(data.added_time, added_time);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn failures_stay_reported() {
+    let (dir, root, mut db) = library("failures", AVATAR);
+    let broken = root.join("Collection/Broken/Broken.nfo");
+    write(&broken, "<movie><title>Broken");
+
+    db.reload().unwrap();
+    assert_eq!(db.scan_report().failures.len(), 1);
+
+    // nothing changed, the file is still broken
+    db.reload().unwrap();
+    assert_eq!(db.scan_report().failures[0].path, broken);
+
+    write_movie(&broken, "Fixed");
+    db.reload().unwrap();
+    assert!(!db.scan_report().has_failures());
+    assert_eq!(all_titles(&mut db), vec!["Avatar", "Fixed"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn progress_is_reported() {
+    let dir = temp_dir("progress");
+    let root = dir.join("library");
+    for i in 0..20 {
+        write_movie(&root.join(format!("Movie {i}/Movie {i}.nfo")), &format!("Movie {i}"));
+    }
+    std::fs::write(root.join("Movie 0/Movie 0.nfo"), "<movie><title>Broken").unwrap();
+
+    let seen = Mutex::new(vec![]);
+    let mut db = Database::with_storage(Box::new(JsonStorage::new(dir.join("kr.json"))));
+    db.load_config_with_progress(std::slice::from_ref(&root), |p| seen.lock().unwrap().push(p))
+        .unwrap();
+
+    let seen = seen.into_inner().unwrap();
+    let last = ScanProgress {
+        dirs: seen.iter().map(|p| p.dirs).max().unwrap(),
+        found: seen.iter().map(|p| p.found).max().unwrap(),
+        parsed: seen.iter().map(|p| p.parsed).max().unwrap(),
+        failed: seen.iter().map(|p| p.failed).max().unwrap(),
+    };
+    assert_eq!(last, ScanProgress { dirs: 21, found: 20, parsed: 19, failed: 1 });
+    assert!(seen.contains(&last));
+    assert_eq!(all_titles(&mut db).len(), 19);
+    assert_eq!(db.scan_report().failures.len(), 1);
+
+    // unchanged directories are still visited, nothing is parsed again
+    let seen = Mutex::new(vec![]);
+    db.load_config_with_progress(&[], |p| seen.lock().unwrap().push(p)).unwrap();
+    let seen = seen.into_inner().unwrap();
+    assert_eq!(seen.iter().map(|p| p.dirs).max(), Some(21));
+    assert_eq!(seen.iter().map(|p| p.parsed).max(), Some(0));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn background_scan() {
+    let (dir, root, db) = library("background", AVATAR);
+    drop(db);
+    write_movie(&root.join("Collection/Heat/Heat.nfo"), "Heat");
+
+    // the cached library is there before anything is scanned
+    let mut db = Database::with_storage(Box::new(JsonStorage::new(dir.join("kr.json"))));
+    db.load_cached(std::slice::from_ref(&root)).unwrap();
+    assert_eq!(all_titles(&mut db), vec!["Avatar"]);
+    let avatar = db.order_by_added_time().next().unwrap().id.clone();
+
+    let scan = db.background_scan();
+    let worker = std::thread::spawn(move || scan.run(|_| {}));
+
+    // changed while the scan runs
+    assert_eq!(db.toggle_fav(&avatar).unwrap(), Some(true));
+
+    let result = worker.join().unwrap();
+    assert_eq!(result.report().added, 1);
+    db.finish_scan(result).unwrap();
+
+    assert_eq!(all_titles(&mut db), vec!["Avatar", "Heat"]);
+    assert!(db.get_movie(&avatar).unwrap().fav);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn full_background_scan() {
+    let (dir, root, mut db) = library("full-background", AVATAR);
+
+    let nfo = root.join("Collection/Avatar/Avatar.nfo");
+    let mtime = std::fs::metadata(&nfo).unwrap().modified().unwrap();
+    write_movie(&nfo, "Avatar 2");
+    File::open(&nfo).unwrap().set_modified(mtime).unwrap();
+
+    let result = db.background_scan().run(|_| {});
+    assert_eq!(result.report().updated, 0);
+    db.finish_scan(result).unwrap();
+    assert_eq!(all_titles(&mut db), vec!["Avatar"]);
+
+    let result = db.background_scan().full().run(|_| {});
+    assert_eq!(result.report().updated, 1);
+    db.finish_scan(result).unwrap();
+    assert_eq!(all_titles(&mut db), vec!["Avatar 2"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn ids_survive_pruning_and_moves() {
+    let (dir, root, mut db) = library("ids", AVATAR);
+    write_movie(&root.join("Collection/Heat/Heat.nfo"), "Heat");
+    db.reload().unwrap();
+
+    let heat = id(&mut db, "heat");
+    db.set_fav(&heat, true).unwrap();
+
+    // Avatar is pruned, Heat moves to another folder
+    std::fs::remove_dir_all(root.join("Collection/Avatar")).unwrap();
+    std::fs::rename(root.join("Collection/Heat"), root.join("Heat")).unwrap();
+    db.reload().unwrap();
+
+    let data = db.get_movie(&heat).unwrap();
+    assert_eq!(data.path, root.join("Heat/Heat.nfo"));
+    assert!(data.fav);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn duplicate_numbers_get_their_own_id() {
+    let (dir, root, mut db) = library("duplicate-ids", AVATAR);
+    for name in ["ABC-123", "ABC-123 (copy)"] {
+        let path = root.join(name).join(format!("{name}.nfo"));
+        write(&path, "<movie><title>Copy</title><num>ABC-123</num></movie>");
+    }
+    db.reload().unwrap();
+
+    let ids: Vec<String> = db.search("copy").map(|item| item.id.to_string()).collect();
+    assert_eq!(ids.len(), 2);
+    assert_ne!(ids[0], ids[1]);
+    assert!(ids.contains(&"num:ABC-123".to_string()));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn user_data_follows_moves() {
+    let (dir, root, mut db) = library("moves", &["Heat/Heat.nfo", "Alien/Alien.nfo", "Alien/Alien.mkv"]);
+    write(
+        &root.join("Ronin/Ronin.nfo"),
+        "<movie><title>Ronin</title><uniqueid type=\"imdb\">tt0122690</uniqueid></movie>",
+    );
+    db.reload().unwrap();
+
+    let movies = [id(&mut db, "heat"), id(&mut db, "alien"), id(&mut db, "ronin")];
+    for movie in &movies {
+        db.set_fav(movie, true).unwrap();
+        db.add_marker(movie, 42.).unwrap();
+        db.save_progress(movie, 600., 6000.).unwrap();
+        db.record_play(movie).unwrap();
+    }
+
+    // the folder is renamed along with the nfo
+    std::fs::rename(root.join("Heat"), root.join("Heat (1995)")).unwrap();
+    std::fs::rename(root.join("Heat (1995)/Heat.nfo"), root.join("Heat (1995)/Heat (1995).nfo")).unwrap();
+    // a new folder, found by its video
+    write_movie(&root.join("Sci-Fi/Alien/Alien.nfo"), "Alien");
+    std::fs::rename(root.join("Alien/Alien.mkv"), root.join("Sci-Fi/Alien/Alien.mkv")).unwrap();
+    std::fs::remove_dir_all(root.join("Alien")).unwrap();
+    // a new folder and nfo name, found by its id
+    write(
+        &root.join("Crime/Ronin 1998/Ronin 1998.nfo"),
+        "<movie><title>Ronin</title><uniqueid type=\"imdb\">tt0122690</uniqueid></movie>",
+    );
+    std::fs::remove_dir_all(root.join("Ronin")).unwrap();
+    // gone, not taken over by any of the new entries
+    write_movie(&root.join("Avatar/Avatar.nfo"), "Avatar");
+    db.reload().unwrap();
+
+    let report = db.scan_report();
+    assert_eq!((report.added, report.updated, report.removed), (1, 3, 0));
+    for movie in &movies {
+        let data = db.get_movie(movie).unwrap();
+        assert!(data.fav, "{:?}", data.path);
+        assert_eq!(data.markers, [42.]);
+        assert_eq!(data.resume_pos, Some(600.));
+        assert_eq!(data.play_count, 1);
+    }
+    assert_eq!(db.get_movie(&movies[0]).unwrap().path, root.join("Heat (1995)/Heat (1995).nfo"));
+
+    let avatar = id(&mut db, "avatar");
+    assert!(!movies.contains(&avatar));
+    assert!(!db.get_movie(&avatar).unwrap().fav);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn different_movies_are_not_merged() {
+    let (dir, root, mut db) = library("not-merged", &["Heat/Heat.nfo"]);
+    let heat = id(&mut db, "heat");
+    db.set_fav(&heat, true).unwrap();
+
+    // same file name but nothing else in common
+    std::fs::remove_file(root.join("Heat/Heat.nfo")).unwrap();
+    write_movie(&root.join("Remakes/Heat/Heat.nfo"), "Heat");
+    db.reload().unwrap();
+
+    let found = db.search("heat").next().unwrap();
+    assert_ne!(*found.id, heat);
+    assert!(!found.movie.fav);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn roots_are_kept_apart() {
+    let (dir, root, _) = library("roots", AVATAR);
+    let other = dir.join("other");
+    write_movie(&other.join("Heat/Heat.nfo"), "Heat");
+
+    let mut db = Database::with_storage(Box::new(JsonStorage::new(dir.join("kr.json"))));
+    db.load_config(&[root.clone(), other.clone()]).unwrap();
+    assert_eq!(all_titles(&mut db), vec!["Avatar", "Heat"]);
+    let heat = id(&mut db, "heat");
+    assert_eq!(db.get_movie(&heat).unwrap().root, other);
+    db.set_fav(&heat, true).unwrap();
+
+    // e.g. an unplugged disk, its entries are hidden but not forgotten
+    db.set_root_enabled(&other, false).unwrap();
+    db.reload().unwrap();
+    assert_eq!(all_titles(&mut db), vec!["Avatar"]);
+    db.set_root_enabled(&other, true).unwrap();
+    assert!(db.get_movie(&heat).unwrap().fav);
+
+    db.remove_root(&root).unwrap();
+    assert_eq!(all_titles(&mut db), vec!["Heat"]);
+    assert_eq!(db.roots().len(), 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn roots_follow_the_search_path() {
+    let (dir, root, mut db) = library("search-path", AVATAR);
+    let other = dir.join("other");
+    write_movie(&other.join("Heat/Heat.nfo"), "Heat");
+    db.sync_roots(&[root.clone(), other.clone()]).unwrap();
+    db.reload().unwrap();
+    let heat = id(&mut db, "heat");
+
+    // started again with `other` left out of the search path
+    let mut db = Database::with_storage(Box::new(JsonStorage::new(dir.join("kr.json"))));
+    db.load_cached(std::slice::from_ref(&root)).unwrap();
+    db.sync_roots(std::slice::from_ref(&root)).unwrap();
+    let result = db.background_scan().run(|_| {});
+    db.finish_scan(result).unwrap();
+    assert_eq!(all_titles(&mut db), vec!["Avatar"]);
+    assert!(!db.roots()[1].enabled);
+    assert!(db.get_movie(&heat).is_some());
+
+    db.sync_roots(&[root, other]).unwrap();
+    assert!(db.roots().iter().all(|r| r.enabled));
+    assert_eq!(all_titles(&mut db), vec!["Avatar", "Heat"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unreadable_library_is_not_overwritten() {
+    let dir = temp_dir("unreadable");
+    let root = dir.join("library");
+    write_movie(&root.join("Heat/Heat.nfo"), "Heat");
+    let path = dir.join("kr.json");
+    write(&path, "{\"movies\": [");
+
+    let mut db = Database::with_storage(Box::new(JsonStorage::new(path.clone())));
+    assert!(db.load_cached(std::slice::from_ref(&root)).is_err());
+    assert!(db.is_read_only());
+
+    let result = db.background_scan().run(|_| {});
+    assert!(db.finish_scan(result).is_err());
+    assert!(db.flush().is_err());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "{\"movies\": [");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn background_scan_keeps_later_changes() {
+    let (dir, root, mut db) = library("merge", AVATAR);
+    write_movie(&root.join("Heat/Heat.nfo"), "Heat");
+    let other = dir.join("other");
+    write_movie(&other.join("Alien/Alien.nfo"), "Alien");
+
+    // a root added after the snapshot is kept, but not scanned by it
+    let scan = db.background_scan();
+    db.load_cached(std::slice::from_ref(&other)).unwrap();
+    db.finish_scan(scan.run(|_| {})).unwrap();
+    assert_eq!(db.roots().len(), 2);
+    assert_eq!(all_titles(&mut db), vec!["Avatar", "Heat"]);
+    db.reload().unwrap();
+    assert_eq!(all_titles(&mut db), vec!["Alien", "Avatar", "Heat"]);
+
+    // the scan ran with the old rules of the root, its result is dropped
+    let scan = db.background_scan();
+    db.set_root_discovery(&root, Discovery::new(Strategy::MovieNfo)).unwrap();
+    assert_eq!(all_titles(&mut db), vec!["Alien"]);
+    write_movie(&root.join("Ronin/Ronin.nfo"), "Ronin");
+    db.finish_scan(scan.run(|_| {})).unwrap();
+
+    assert_eq!(all_titles(&mut db), vec!["Alien"]);
+    assert_eq!(db.roots()[0].discovery.strategy, Strategy::MovieNfo);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn generic_video_names_do_not_merge_movies() {
+    let files = &["Heat/movie.nfo", "Heat/movie.mkv", "Alien/movie.nfo", "Alien/movie.mkv", "Ronin/movie.nfo"];
+    let (dir, root, mut db) = library("generic-videos", files);
+    write(&root.join("Ronin/ronin-1080p.mkv"), [0]);
+    db.set_root_discovery(&root, Discovery::new(Strategy::MovieNfo)).unwrap();
+    for name in ["Heat", "Alien", "Ronin"] {
+        write_movie(&root.join(name).join("movie.nfo"), name);
+    }
+    db.reload().unwrap();
+
+    let (heat, alien, ronin) = (id(&mut db, "heat"), id(&mut db, "alien"), id(&mut db, "ronin"));
+    for movie in [&heat, &alien, &ronin] {
+        db.set_fav(movie, true).unwrap();
+    }
+
+    // two other movies with the same generic video names
+    for (old, new) in [("Heat", "Solaris"), ("Alien", "Brazil")] {
+        std::fs::remove_dir_all(root.join(old)).unwrap();
+        write_movie(&root.join(new).join("movie.nfo"), new);
+        write(&root.join(new).join("movie.mkv"), [0]);
+    }
+    // a new title, but the only video of that name
+    std::fs::rename(root.join("Ronin"), root.join("Ronin (1998)")).unwrap();
+    write_movie(&root.join("Ronin (1998)/movie.nfo"), "Ronin: Director's Cut");
+    db.reload().unwrap();
+
+    for title in ["solaris", "brazil"] {
+        let found = db.search(title).next().unwrap();
+        assert!(![&heat, &alien].contains(&found.id));
+        assert!(!found.movie.fav);
+    }
+    assert!(db.get_movie(&ronin).unwrap().fav);
+    assert!(db.get_movie(&heat).is_none());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,112 @@
+use std::{
+    fs::File,
+    time::{Duration, SystemTime},
+};
+
+use common::{library_with, titles, write_movie};
+use kr::db::{normalize, tokenize};
+
+mod common;
+
+const LIBRARY: &[(&str, &str)] = &[
+    (
+        "Amelie/Amelie.nfo",
+        "<movie><title>Le Fabuleux Destin d'Amélie Poulain</title></movie>",
+    ),
+    ("Spirited Away/Spirited Away.nfo", "<movie><title>千と千尋の神隠し</title></movie>"),
+    (
+        "Heat/Heat.nfo",
+        "<movie><title>Heat</title><actor><name>Al Pacino</name></actor><actor><name>Robert De Niro</name></actor></movie>",
+    ),
+    (
+        "Godfather/Godfather.nfo",
+        "<movie><title>The Godfather</title><actor><name>Al Pacino</name></actor></movie>",
+    ),
+];
+
+#[test]
+fn tokens() {
+    assert_eq!(normalize("Émile ＦＵＬＬ"), "emile full");
+    assert_eq!(tokenize("Heat (1995)"), vec!["heat", "1995"]);
+    assert_eq!(tokenize("千と千尋"), vec!["千と", "と千", "千尋", "尋"]);
+    assert_eq!(tokenize("ABC-123 東京"), vec!["abc", "123", "東京", "京"]);
+    assert_eq!(tokenize("기생충"), vec!["기생", "생충", "충"]);
+    assert_eq!(normalize("ば"), "ば");
+    assert_ne!(normalize("ば"), normalize("は"));
+    assert_eq!(normalize("ﾊﾞｽ"), "バス");
+}
+
+#[test]
+fn accents_are_ignored() {
+    let (dir, _, mut db) = library_with("accents", LIBRARY);
+    let amelie = vec!["Le Fabuleux Destin d'Amélie Poulain"];
+
+    assert_eq!(titles(db.search("amelie")), amelie);
+    assert_eq!(titles(db.search("AMÉLIE")), amelie);
+    assert_eq!(titles(db.search("Ａｍｅｌｉｅ")), amelie);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn cjk_substrings() {
+    let (dir, _, mut db) = library_with("cjk", LIBRARY);
+    let spirited = vec!["千と千尋の神隠し"];
+
+    assert_eq!(titles(db.search("千尋")), spirited);
+    assert_eq!(titles(db.search("神隠し")), spirited);
+    assert_eq!(titles(db.search("隠")), spirited);
+    // the characters are there, but not next to each other
+    assert!(titles(db.search("千隠")).is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn every_term_has_to_match() {
+    let (dir, _, mut db) = library_with("and", LIBRARY);
+
+    let mut found = titles(db.search("pacino"));
+    found.sort();
+    assert_eq!(found, vec!["Heat", "The Godfather"]);
+
+    assert_eq!(titles(db.search("pacino niro")), vec!["Heat"]);
+    assert_eq!(titles(db.search("al god")), vec!["The Godfather"]);
+    assert!(titles(db.search("godfather niro")).is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn index_follows_the_library() {
+    let (dir, root, mut db) = library_with("invalidation", LIBRARY);
+    assert_eq!(titles(db.search("heat")), vec!["Heat"]);
+
+    let nfo = root.join("Heat/Heat.nfo");
+    write_movie(&nfo, "Ronin");
+    // well after the last scan
+    let later = SystemTime::now() + Duration::from_secs(60);
+    File::open(&nfo).unwrap().set_modified(later).unwrap();
+    db.reload().unwrap();
+
+    // the same query again, not the cached result
+    assert!(titles(db.search("heat")).is_empty());
+    assert_eq!(titles(db.search("ronin")), vec!["Ronin"]);
+
+    db.set_root_enabled(&root, false).unwrap();
+    assert!(titles(db.search("ronin")).is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn korean_and_voiced_kana() {
+    let (dir, root, mut db) = library_with("korean", LIBRARY);
+    write_movie(&root.join("Parasite/Parasite.nfo"), "기생충");
+    write_movie(&root.join("Bus/Bus.nfo"), "バス");
+    write_movie(&root.join("Lotus/Lotus.nfo"), "ハス");
+    db.reload().unwrap();
+
+    assert_eq!(titles(db.search("생충")), vec!["기생충"]);
+    assert_eq!(titles(db.search("バス")), vec!["バス"]);
+    assert_eq!(titles(db.search("ハス")), vec!["ハス"]);
+    // half width with a separate voicing mark
+    assert_eq!(titles(db.search("ﾊﾞｽ")), vec!["バス"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,95 @@
+use std::cmp::Ordering;
+
+use common::{id, library_with, titles};
+use kr::db::{SortKey, SortOrder, natural_cmp};
+
+mod common;
+
+/// Three movies, one of them with a `sorttitle` and one without num or year.
+const LIBRARY: &[(&str, &str)] = &[
+    (
+        "ABC-9/ABC-9.nfo",
+        "<movie><title>The Matrix</title><sorttitle>Matrix</sorttitle><num>ABC-9</num><year>1999</year></movie>",
+    ),
+    (
+        "ABC-10/ABC-10.nfo",
+        "<movie><title>Solaris</title><num>ABC-10</num><year>1972</year></movie>",
+    ),
+    ("Alien/Alien.nfo", "<movie><title>Alien</title></movie>"),
+];
+
+#[test]
+fn natural_order() {
+    assert_eq!(natural_cmp("ABC-9", "ABC-10"), Ordering::Less);
+    assert_eq!(natural_cmp("ABC-010", "ABC-9"), Ordering::Greater);
+    assert_eq!(natural_cmp("Movie 2", "movie 10"), Ordering::Less);
+    assert_eq!(natural_cmp("Émile", "emile"), Ordering::Equal);
+    // numbers come before text
+    assert_eq!(natural_cmp("2001", "Alien"), Ordering::Less);
+}
+
+#[test]
+fn title_uses_sorttitle() {
+    let (dir, _, mut db) = library_with("sorttitle", LIBRARY);
+
+    let sorted = titles(db.sort(SortKey::Title, SortOrder::Ascending));
+    assert_eq!(sorted, vec!["Alien", "The Matrix", "Solaris"]);
+
+    let sorted = titles(db.sort(SortKey::Title, SortOrder::Descending));
+    assert_eq!(sorted, vec!["Solaris", "The Matrix", "Alien"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn direction_keeps_missing_values_last() {
+    let (dir, _, mut db) = library_with("direction", LIBRARY);
+
+    let sorted = titles(db.sort(SortKey::Num, SortOrder::Ascending));
+    assert_eq!(sorted, vec!["The Matrix", "Solaris", "Alien"]);
+
+    let sorted = titles(db.sort(SortKey::Num, SortOrder::Descending));
+    assert_eq!(sorted, vec!["Solaris", "The Matrix", "Alien"]);
+
+    let sorted = titles(db.sort(SortKey::ReleaseDate, SortOrder::Descending));
+    assert_eq!(sorted, vec!["The Matrix", "Solaris", "Alien"]);
+
+    // nothing played yet, only the order of the ids is left
+    let ascending = titles(db.sort(SortKey::LastPlayed, SortOrder::Ascending));
+    let descending = titles(db.sort(SortKey::LastPlayed, SortOrder::Descending));
+    assert_eq!(ascending, descending);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn favorites_and_unwatched_are_sorted() {
+    let (dir, _, mut db) = library_with("subsets", LIBRARY);
+    let (alien, solaris, matrix) = (id(&mut db, "alien"), id(&mut db, "solaris"), id(&mut db, "matrix"));
+
+    db.set_fav(&alien, true).unwrap();
+    db.set_fav(&solaris, true).unwrap();
+    db.set_watched(&matrix, true).unwrap();
+
+    assert_eq!(titles(db.sort_fav(SortKey::Title, SortOrder::Ascending)), vec!["Alien", "Solaris"]);
+    assert_eq!(titles(db.sort_fav(SortKey::Title, SortOrder::Descending)), vec!["Solaris", "Alien"]);
+    assert_eq!(titles(db.sort_unwatched(SortKey::Num, SortOrder::Ascending)), vec!["Solaris", "Alien"]);
+
+    db.set_fav(&solaris, false).unwrap();
+    assert_eq!(titles(db.sort_fav(SortKey::Title, SortOrder::Ascending)), vec!["Alien"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn ties_are_ordered_by_id() {
+    let (dir, root, mut db) = library_with("ties", LIBRARY);
+
+    // scanned at once, the added times are all the same
+    let order = titles(db.order_by_added_time());
+    assert_eq!(order, vec!["Alien", "Solaris", "The Matrix"]);
+
+    // a moved entry gets a new position but keeps its id and added time
+    std::fs::create_dir_all(root.join("Sci-Fi")).unwrap();
+    std::fs::rename(root.join("ABC-9"), root.join("Sci-Fi/ABC-9")).unwrap();
+    db.reload().unwrap();
+    assert_eq!(titles(db.order_by_added_time()), order);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
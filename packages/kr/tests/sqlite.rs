@@ -0,0 +1,224 @@
+#![cfg(feature = "sqlite")]
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use common::{id, temp_dir, titles, write_files};
+use kr::db::{Database, JsonStorage, SqliteStorage, Storage, StorageQuery, import};
+use rusqlite::Connection;
+
+mod common;
+
+/// A library with actors, tags, an accented title and one full of `LIKE` wildcards.
+fn library(name: &str) -> (PathBuf, PathBuf) {
+    let dir = temp_dir(name);
+    let root = dir.join("library");
+    write_files(
+        &root,
+        &[
+            (
+                "Heat/Heat.nfo",
+                "<movie><title>Heat</title><actor><name>Al Pacino</name></actor><tag>crime</tag></movie>",
+            ),
+            (
+                "Godfather/Godfather.nfo",
+                "<movie><title>The Godfather</title><actor><name>Al Pacino</name></actor><tag>mafia</tag></movie>",
+            ),
+            ("Percent/Percent.nfo", "<movie><title>100% Wolf_2</title></movie>"),
+            (
+                "Amelie/Amelie.nfo",
+                "<movie><title>Le Fabuleux Destin d'Amélie Poulain</title></movie>",
+            ),
+        ],
+    );
+    (dir, root)
+}
+
+fn open(path: &Path, root: &Path) -> Database {
+    let mut db = Database::with_storage(Box::new(SqliteStorage::open(path).unwrap()));
+    db.load_config(&[root.to_path_buf()]).unwrap();
+    db
+}
+
+#[test]
+fn roundtrip() {
+    let (dir, root) = library("roundtrip");
+    let path = dir.join("kr.sqlite");
+    let mut db = open(&path, &root);
+    let heat = id(&mut db, "heat");
+    let order = titles(db.order_by_added_time());
+
+    db.set_fav(&heat, true).unwrap();
+    db.add_marker(&heat, 42.).unwrap();
+    db.save_progress(&heat, 600., 6000.).unwrap();
+
+    let mut db = open(&path, &root);
+    let data = db.get_movie(&heat).unwrap();
+    assert!(data.fav);
+    assert_eq!(data.markers, [42.]);
+    assert_eq!(data.resume_pos, Some(600.));
+    assert_eq!(titles(db.order_by_added_time()), order);
+
+    // a removed movie loses its row
+    std::fs::remove_dir_all(root.join("Godfather")).unwrap();
+    db.reload().unwrap();
+    let config = SqliteStorage::open(&path).unwrap().load().unwrap().unwrap();
+    assert_eq!(config.movies.len(), 3);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unchanged_rows_are_not_written() {
+    let (dir, root) = library("unchanged");
+    let path = dir.join("kr.sqlite");
+    let mut db = open(&path, &root);
+    let heat = id(&mut db, "heat");
+    let godfather = id(&mut db, "godfather");
+
+    // a mark only a rewrite of the row would remove
+    let conn = Connection::open(&path).unwrap();
+    conn.execute("UPDATE movies SET title = 'untouched'", []).unwrap();
+
+    db.set_fav(&heat, true).unwrap();
+    db.flush().unwrap();
+
+    let title = |id: &kr::db::MovieId| -> String {
+        conn.query_row("SELECT title FROM movies WHERE id = ?1", [id.as_str()], |row| row.get(0))
+            .unwrap()
+    };
+    assert_eq!(title(&heat), "Heat");
+    assert_eq!(title(&godfather), "untouched");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn import_from_json() {
+    let (dir, root) = library("import");
+    let json = JsonStorage::new(dir.join("kr.json"));
+    let mut db = Database::with_storage(Box::new(JsonStorage::new(dir.join("kr.json"))));
+    db.load_config(std::slice::from_ref(&root)).unwrap();
+    let heat = id(&mut db, "heat");
+    db.set_fav(&heat, true).unwrap();
+
+    let sqlite = SqliteStorage::open(&dir.join("kr.sqlite")).unwrap();
+    assert!(import(&json, &sqlite).unwrap());
+    // only into an empty database
+    assert!(!import(&json, &sqlite).unwrap());
+
+    let imported = sqlite.load().unwrap().unwrap();
+    let stored = json.load().unwrap().unwrap();
+    assert_eq!(imported.roots.len(), 1);
+    let ids = |config: &kr::db::Config| config.movies.iter().map(|d| d.id.clone()).collect::<Vec<_>>();
+    assert_eq!(ids(&imported), ids(&stored));
+    assert!(imported.movies.iter().any(|d| d.id == heat && d.fav));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn queries_match_the_linear_scan() {
+    let (dir, root) = library("query");
+    let path = dir.join("kr.sqlite");
+    let mut db = open(&path, &root);
+    let godfather = id(&mut db, "godfather");
+    db.set_fav(&godfather, true).unwrap();
+
+    let sqlite = SqliteStorage::open(&path).unwrap();
+    let config = sqlite.load().unwrap().unwrap();
+    let json = JsonStorage::new(dir.join("unused.json"));
+
+    let hour_ago = SystemTime::now() - Duration::from_secs(3600);
+    let queries = [
+        StorageQuery::Title("father"),
+        StorageQuery::Title("100%"),
+        StorageQuery::Title("f_2"),
+        // only ascii case is folded by sqlite itself
+        StorageQuery::Title("AMÉLIE"),
+        StorageQuery::Title("amelie"),
+        StorageQuery::Actor("Al Pacino"),
+        StorageQuery::Tag("mafia"),
+        StorageQuery::Fav,
+        StorageQuery::AddedAfter(hour_ago),
+        StorageQuery::AddedAfter(SystemTime::now() + Duration::from_secs(3600)),
+    ];
+    let expected = [1, 1, 1, 1, 1, 2, 1, 1, 4, 0];
+
+    for (query, expected) in queries.iter().zip(expected) {
+        let found = sqlite.query(&config, query).unwrap();
+        assert_eq!(found, json.query(&config, query).unwrap(), "{query:?}");
+        assert_eq!(found.len(), expected, "{query:?}");
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rows_keyed_by_path_are_upgraded() {
+    let (dir, root) = library("upgrade");
+    let mut db = Database::with_storage(Box::new(JsonStorage::new(dir.join("kr.json"))));
+    db.load_config(std::slice::from_ref(&root)).unwrap();
+    let heat = id(&mut db, "heat");
+    let config = JsonStorage::new(dir.join("kr.json")).load().unwrap().unwrap();
+
+    // the first layout of the database
+    let path = dir.join("kr.sqlite");
+    let conn = Connection::open(&path).unwrap();
+    conn.execute_batch(
+        "CREATE TABLE meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+         CREATE TABLE movies (path TEXT PRIMARY KEY, pos INTEGER NOT NULL, title TEXT NOT NULL,
+             fav INTEGER NOT NULL, added_time INTEGER NOT NULL, data TEXT NOT NULL);
+         CREATE TABLE actors (path TEXT NOT NULL, name TEXT NOT NULL);
+         CREATE TABLE tags (path TEXT NOT NULL, name TEXT NOT NULL);",
+    )
+    .unwrap();
+
+    let mut head = serde_json::to_value(&config).unwrap();
+    head["movies"] = serde_json::Value::Array(vec![]);
+    conn.execute("INSERT INTO meta (key, value) VALUES ('config', ?1)", [head.to_string()])
+        .unwrap();
+    for (pos, item) in config.movies.iter().enumerate() {
+        conn.execute(
+            "INSERT INTO movies (path, pos, title, fav, added_time, data) VALUES (?1, ?2, ?3, 0, 0, ?4)",
+            rusqlite::params![
+                item.path.to_string_lossy(),
+                pos as i64,
+                item.movie.title,
+                serde_json::to_string(item).unwrap()
+            ],
+        )
+        .unwrap();
+    }
+    drop(conn);
+
+    let sqlite = SqliteStorage::open(&path).unwrap();
+    let upgraded = sqlite.load().unwrap().unwrap();
+    assert_eq!(upgraded.movies.len(), 4);
+    assert_eq!(sqlite.query(&upgraded, &StorageQuery::Actor("Al Pacino")).unwrap().len(), 2);
+
+    let db = open(&path, &root);
+    assert_eq!(db.get_movie(&heat).unwrap().movie.title, "Heat");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rows_without_title_key_are_upgraded() {
+    let (dir, root) = library("title-key");
+    let path = dir.join("kr.sqlite");
+    let db = open(&path, &root);
+    drop(db);
+
+    // the layout before titles were normalized
+    let conn = Connection::open(&path).unwrap();
+    conn.execute_batch(
+        "ALTER TABLE movies DROP COLUMN title_key;
+         PRAGMA user_version = 1;",
+    )
+    .unwrap();
+    drop(conn);
+
+    let sqlite = SqliteStorage::open(&path).unwrap();
+    let config = sqlite.load().unwrap().unwrap();
+    assert_eq!(config.movies.len(), 4);
+    assert_eq!(sqlite.query(&config, &StorageQuery::Title("amélie")).unwrap().len(), 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,184 @@
+use std::path::PathBuf;
+
+use common::{library_with, titles, write};
+use kr::{
+    Movie, TvShow,
+    db::{Database, MovieId},
+};
+
+mod common;
+
+fn fixture(name: &str) -> String {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/tv")
+        .join(name);
+    std::fs::read_to_string(path).unwrap()
+}
+
+fn episode(title: &str, season: u32, episode: u32) -> String {
+    format!(
+        "<episodedetails><title>{title}</title><season>{season}</season><episode>{episode}</episode></episodedetails>"
+    )
+}
+
+/// A library with a movie and a show, stored in its own temp dir.
+fn library(name: &str) -> (PathBuf, PathBuf, Database) {
+    library_with(
+        name,
+        &[
+            (
+                "ABC-123/ABC-123.nfo",
+                "<movie><title>A Movie</title><num>ABC-123</num></movie>".to_string(),
+            ),
+            ("Breaking Bad/tvshow.nfo", fixture("tvshow.nfo")),
+            (
+                "Breaking Bad/Season 01/season.nfo",
+                "<season><title>Season 1</title></season>".to_string(),
+            ),
+            (
+                "Breaking Bad/Season 01/Breaking Bad - S01E02.nfo",
+                episode("Cat's in the Bag...", 1, 2),
+            ),
+            ("Breaking Bad/Season 01/Breaking Bad - S01E01.nfo", fixture("episode.nfo")),
+            (
+                "Breaking Bad/Season 02/Breaking Bad - S02E01.nfo",
+                episode("Seven Thirty-Seven", 2, 1),
+            ),
+            // no numbers in the nfo, taken from the file name
+            (
+                "Breaking Bad/Specials/Breaking Bad - S00E01.nfo",
+                "<episodedetails><title>Special</title></episodedetails>".to_string(),
+            ),
+        ],
+    )
+}
+
+#[test]
+fn parse_tvshow() {
+    let show = TvShow::from_nfo(&fixture("tvshow.nfo")).unwrap();
+
+    assert_eq!(show.title, "Breaking Bad");
+    assert_eq!(show.status.as_deref(), Some("Ended"));
+    assert_eq!(show.year, Some(2008));
+    assert_eq!(
+        show.thumb_path(),
+        Some("https://assets.fanart.tv/fanart/tv/81189/tvthumb/breaking-bad.jpg")
+    );
+    assert_eq!(
+        show.season_thumb_path(2),
+        Some("https://artworks.thetvdb.com/banners/seasons/81189-2.jpg")
+    );
+    assert_eq!(show.season_thumb_path(3), None);
+    assert_eq!(show.season_name(2), Some("The Second Season"));
+    assert_eq!(show.actor[0].role.as_deref(), Some("Walter White"));
+    assert_eq!(show.uniqueid.as_ref().unwrap().len(), 3);
+}
+
+#[test]
+fn parse_episode() {
+    let episode = Movie::from_nfo(&fixture("episode.nfo")).unwrap();
+
+    assert_eq!(episode.title, "Pilot");
+    assert_eq!(episode.showtitle.as_deref(), Some("Breaking Bad"));
+    assert_eq!(episode.season, Some(1));
+    assert_eq!(episode.episode, Some(1));
+    assert_eq!(episode.aired.as_deref(), Some("2008-01-20"));
+    assert_eq!(episode.default_rating(), Some(8.2));
+    assert!(episode.actor.is_empty());
+}
+
+#[test]
+fn scan_shows() {
+    let (dir, _, mut db) = library("scan-shows");
+
+    // episodes are not listed with the movies
+    assert_eq!(titles(db.order_by_added_time()), vec!["A Movie"]);
+
+    let shows = db.shows();
+    assert_eq!(shows.len(), 1);
+    assert_eq!(shows[0].show.title, "Breaking Bad");
+    let show = shows[0].path.clone();
+    assert_eq!(show, dir.join("library/Breaking Bad/tvshow.nfo"));
+    assert_eq!(db.get_show(&show).unwrap().show.title, "Breaking Bad");
+
+    assert_eq!(db.seasons(&show), vec![1, 2, 0]);
+    assert_eq!(titles(db.episodes(&show, Some(1))), vec!["Pilot", "Cat's in the Bag..."]);
+    assert_eq!(titles(db.episodes(&show, Some(0))), vec!["Special"]);
+    assert_eq!(db.episodes(&show, None).count(), 4);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn next_episode() {
+    let (dir, _, mut db) = library("next-episode");
+    let show = db.shows()[0].path.clone();
+
+    let episodes: Vec<MovieId> = db.episodes(&show, None).map(|item| item.id.clone()).collect();
+    // specials first, then S01E01, S01E02, S02E01
+    let [special, first, second, third] = &episodes[..] else {
+        panic!("unexpected episodes {episodes:?}");
+    };
+
+    assert_eq!(db.next_episode(first).as_ref(), Some(second));
+    assert_eq!(db.next_episode(second).as_ref(), Some(third));
+    assert_eq!(db.next_episode(third), None);
+    assert_eq!(db.next_episode(special), None);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn episodes_keep_user_data_across_reloads() {
+    let (dir, _, mut db) = library("reload");
+    let show = db.shows()[0].path.clone();
+    let first = db.episodes(&show, Some(1)).next().unwrap().id.clone();
+
+    db.set_watched(&first, true).unwrap();
+    db.reload().unwrap();
+
+    assert_eq!(db.shows().len(), 1);
+    let data = db.get_movie(&first).unwrap();
+    assert!(data.watched);
+    assert!(data.is_episode());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn removing_a_root_forgets_its_shows() {
+    let (dir, root, mut db) = library("remove-root");
+    assert_eq!(db.shows().len(), 1);
+
+    db.remove_root(&root).unwrap();
+    assert!(db.shows().is_empty());
+    assert_eq!(db.order_by_added_time().count(), 0);
+
+    // the root is not scanned back in either
+    db.reload().unwrap();
+    assert!(db.roots().is_empty());
+    assert!(db.shows().is_empty());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn shows_are_keyed_by_their_nfo() {
+    let (dir, root, mut db) = library("show-keys");
+    let saul = root.join("Better Call Saul");
+    write(&saul.join("tvshow.nfo"), "<tvshow><title>Better Call Saul</title></tvshow>");
+    write(&saul.join("Season 01/Better Call Saul - S01E01.nfo"), episode("Uno", 1, 1));
+    db.reload().unwrap();
+
+    let keys: Vec<PathBuf> = db.shows().iter().map(|s| s.path.clone()).collect();
+    assert_eq!(keys, vec![saul.join("tvshow.nfo"), root.join("Breaking Bad/tvshow.nfo")]);
+
+    // the other show goes away, the key still finds the same show
+    std::fs::remove_dir_all(root.join("Breaking Bad")).unwrap();
+    db.reload().unwrap();
+    assert!(db.get_show(&keys[1]).is_none());
+    assert_eq!(db.get_show(&keys[0]).unwrap().show.title, "Better Call Saul");
+    assert_eq!(titles(db.episodes(&keys[0], None)), vec!["Uno"]);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,197 @@
+use std::{
+    thread::sleep,
+    time::{Duration, SystemTime},
+};
+
+use common::{id, library, open, titles};
+use kr::db::MovieId;
+
+mod common;
+
+const LIBRARY: &[&str] = &["Alien/Alien.nfo", "Avatar/Avatar.nfo", "Heat/Heat.nfo"];
+
+#[test]
+fn markers() {
+    let (dir, root, mut db) = library("markers", LIBRARY);
+    let heat = id(&mut db, "heat");
+    let alien = id(&mut db, "alien");
+
+    assert_eq!(db.add_marker(&heat, 300.).unwrap(), Some(&[300.][..]));
+    assert_eq!(db.add_marker(&heat, 12.5).unwrap(), Some(&[12.5, 300.][..]));
+    // too close to an existing marker
+    assert_eq!(db.add_marker(&heat, 300.2).unwrap(), Some(&[12.5, 300.][..]));
+    assert_eq!(db.add_marker(&heat, 120.).unwrap(), Some(&[12.5, 120., 300.][..]));
+
+    assert!(db.remove_marker(&heat, 119.8).unwrap());
+    assert!(!db.remove_marker(&heat, 60.).unwrap());
+
+    let db = open(&dir, &root);
+    assert_eq!(db.markers(&heat), [12.5, 300.]);
+    assert!(db.markers(&alien).is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn markers_survive_a_changed_nfo() {
+    let (dir, root, mut db) = library("markers-rescan", LIBRARY);
+    let heat = id(&mut db, "heat");
+    db.add_marker(&heat, 42.).unwrap();
+
+    let nfo = root.join("Heat/Heat.nfo");
+    common::write_movie(&nfo, "Heat (1995)");
+    // edited well after the last scan
+    let later = SystemTime::now() + Duration::from_secs(60);
+    std::fs::File::open(&nfo).unwrap().set_modified(later).unwrap();
+    db.reload().unwrap();
+    assert_eq!(db.get_movie(&heat).unwrap().movie.title, "Heat (1995)");
+    assert_eq!(db.markers(&heat), [42.]);
+
+    // nothing to mark on an entry that isn't in the library
+    assert_eq!(db.add_marker(&MovieId::random(), 1.).unwrap(), None);
+    assert!(!db.remove_marker(&MovieId::random(), 1.).unwrap());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn resume_position() {
+    let (dir, root, mut db) = library("resume", LIBRARY);
+    let heat = id(&mut db, "heat");
+    let alien = id(&mut db, "alien");
+
+    db.save_progress(&heat, 1200., 6000.).unwrap();
+    assert_eq!(db.resume_pos(&heat), Some(1200.));
+
+    // not worth resuming from the first seconds
+    db.save_progress(&alien, 3., 6000.).unwrap();
+    assert_eq!(db.resume_pos(&alien), None);
+
+    let mut db = open(&dir, &root);
+    assert_eq!(db.resume_pos(&heat), Some(1200.));
+    assert!(!db.get_movie(&heat).unwrap().watched);
+
+    // into the credits, the movie counts as watched but can still be resumed
+    db.save_progress(&heat, 5700., 6000.).unwrap();
+    assert_eq!(db.resume_pos(&heat), Some(5700.));
+    assert!(db.get_movie(&heat).unwrap().watched);
+
+    // played to the end, nothing left to resume
+    db.save_progress(&heat, 5995., 6000.).unwrap();
+    assert_eq!(db.resume_pos(&heat), None);
+
+    let mut db = open(&dir, &root);
+    assert_eq!(db.resume_pos(&heat), None);
+    assert_eq!(titles(db.filter_by_unwatched()).len(), 2);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn start_from_the_beginning() {
+    let (dir, root, mut db) = library("restart", LIBRARY);
+    let heat = id(&mut db, "heat");
+
+    db.save_progress(&heat, 1200., 6000.).unwrap();
+    // playing from the start instead of resuming drops the old position
+    db.set_resume_pos(&heat, None).unwrap();
+    assert_eq!(db.resume_pos(&heat), None);
+
+    db.save_progress(&heat, 1800., 6000.).unwrap();
+    // stopped again within the first seconds
+    db.save_progress(&heat, 2., 6000.).unwrap();
+    assert_eq!(db.resume_pos(&heat), None);
+
+    let db = open(&dir, &root);
+    assert_eq!(db.resume_pos(&heat), None);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn favorites() {
+    let (dir, root, mut db) = library("fav", LIBRARY);
+    let heat = id(&mut db, "heat");
+    let alien = id(&mut db, "alien");
+
+    assert_eq!(db.toggle_fav(&heat).unwrap(), Some(true));
+    assert_eq!(db.toggle_fav(&alien).unwrap(), Some(true));
+    assert_eq!(db.toggle_fav(&alien).unwrap(), Some(false));
+    assert_eq!(titles(db.filter_by_fav()), vec!["Heat"]);
+
+    let mut db = open(&dir, &root);
+    assert!(db.get_movie(&heat).unwrap().fav);
+    assert_eq!(titles(db.filter_by_fav()), vec!["Heat"]);
+
+    db.set_fav(&heat, false).unwrap();
+    assert_eq!(db.filter_by_fav().count(), 0);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn watch_history() {
+    let (dir, root, mut db) = library("history", LIBRARY);
+    let heat = id(&mut db, "heat");
+    let alien = id(&mut db, "alien");
+    assert_eq!(db.order_by_last_played().count(), 0);
+
+    db.record_play(&heat).unwrap();
+    sleep(Duration::from_millis(10));
+    db.record_play(&alien).unwrap();
+    assert_eq!(titles(db.order_by_last_played()), vec!["Alien", "Heat"]);
+
+    sleep(Duration::from_millis(10));
+    db.record_play(&heat).unwrap();
+    assert_eq!(titles(db.order_by_last_played()), vec!["Heat", "Alien"]);
+
+    let mut db = open(&dir, &root);
+    assert_eq!(titles(db.order_by_last_played()), vec!["Heat", "Alien"]);
+    assert_eq!(db.get_movie(&heat).unwrap().play_count, 2);
+    assert_eq!(db.get_movie(&alien).unwrap().play_count, 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn watched_after_most_of_the_movie() {
+    let (dir, root, mut db) = library("watched", LIBRARY);
+    let heat = id(&mut db, "heat");
+    let alien = id(&mut db, "alien");
+
+    db.record_play(&heat).unwrap();
+    db.save_progress(&heat, 4000., 6000.).unwrap();
+    assert!(!db.get_movie(&heat).unwrap().watched);
+
+    sleep(Duration::from_millis(10));
+    db.record_play(&alien).unwrap();
+    db.save_progress(&alien, 5500., 6000.).unwrap();
+    assert!(db.get_movie(&alien).unwrap().watched);
+    let mut unwatched = titles(db.filter_by_unwatched());
+    unwatched.sort();
+    assert_eq!(unwatched, vec!["Avatar", "Heat"]);
+    // started but not finished, still recently watched
+    assert_eq!(titles(db.order_by_last_played()), vec!["Alien", "Heat"]);
+
+    let mut db = open(&dir, &root);
+    db.set_watched(&alien, false).unwrap();
+    assert_eq!(titles(db.filter_by_unwatched()).len(), 3);
+    assert_eq!(db.get_movie(&alien).unwrap().play_count, 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unwatched_newest_first() {
+    let (dir, root, mut db) = library("unwatched", &["Alien/Alien.nfo", "Avatar/Avatar.nfo"]);
+    sleep(Duration::from_millis(10));
+    common::write_movie(&root.join("Heat/Heat.nfo"), "Heat");
+    db.reload().unwrap();
+    assert_eq!(titles(db.filter_by_unwatched())[0], "Heat");
+
+    // moved entries come last in the library, that doesn't make them new
+    std::fs::create_dir_all(root.join("Sci-Fi")).unwrap();
+    std::fs::rename(root.join("Alien"), root.join("Sci-Fi/Alien")).unwrap();
+    db.reload().unwrap();
+    let unwatched = titles(db.filter_by_unwatched());
+    assert_eq!(unwatched.len(), 3);
+    assert_eq!(unwatched[0], "Heat");
+
+    let heat = id(&mut db, "heat");
+    db.set_watched(&heat, true).unwrap();
+    assert_eq!(titles(db.filter_by_unwatched()).len(), 2);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
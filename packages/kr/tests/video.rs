@@ -0,0 +1,125 @@
+use std::path::{Path, PathBuf};
+
+use common::{id, library, temp_dir, tree, write};
+use kr::video::{DEFAULT_EXTENSIONS, find_videos, split_part};
+
+mod common;
+
+fn touch(path: &Path, len: usize) {
+    write(path, vec![0u8; len]);
+}
+
+fn folder(name: &str, files: &[&str]) -> PathBuf {
+    let dir = temp_dir(name);
+    tree(&dir, files);
+    dir
+}
+
+fn extensions() -> Vec<String> {
+    DEFAULT_EXTENSIONS.map(String::from).to_vec()
+}
+
+fn names(videos: Vec<PathBuf>) -> Vec<String> {
+    videos
+        .iter()
+        .map(|v| v.file_name().unwrap().to_string_lossy().into_owned())
+        .collect()
+}
+
+#[test]
+fn part_names() {
+    assert_eq!(split_part("Movie-cd1"), Some(("Movie", 1)));
+    assert_eq!(split_part("Movie.part2"), Some(("Movie", 2)));
+    assert_eq!(split_part("Movie pt 3"), Some(("Movie", 3)));
+    assert_eq!(split_part("Movie_Disc10"), Some(("Movie", 10)));
+    assert_eq!(split_part("ABC-123"), None);
+    assert_eq!(split_part("Concord1"), None);
+    assert_eq!(split_part("cd1"), None);
+}
+
+#[test]
+fn single_video_in_preferred_format() {
+    let dir = folder("single", &["Heat.nfo", "Heat.mkv", "Heat.MP4", "Heat.srt", "Heat-trailer.mp4"]);
+
+    let videos = find_videos(&dir.join("Heat.nfo"), &extensions()).unwrap();
+    assert_eq!(names(videos), vec!["Heat.MP4"]);
+
+    let videos = find_videos(&dir.join("Heat.nfo"), &["mkv".to_string()]).unwrap();
+    assert_eq!(names(videos), vec!["Heat.mkv"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn parts_are_ordered() {
+    let dir = folder("parts", &[
+        "Heat.nfo",
+        "Heat-cd10.avi",
+        "Heat-cd2.avi",
+        "Heat-cd1.avi",
+        "Heat-cd1.mkv",
+        "Other-cd1.avi",
+    ]);
+
+    let videos = find_videos(&dir.join("Heat.nfo"), &extensions()).unwrap();
+    assert_eq!(names(videos), vec!["Heat-cd1.mkv", "Heat-cd2.avi", "Heat-cd10.avi"]);
+
+    // an nfo named after the first part
+    std::fs::rename(dir.join("Heat.nfo"), dir.join("Heat-cd1.nfo")).unwrap();
+    let videos = find_videos(&dir.join("Heat-cd1.nfo"), &extensions()).unwrap();
+    assert_eq!(names(videos), vec!["Heat-cd1.mkv", "Heat-cd2.avi", "Heat-cd10.avi"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn generic_movie_nfo() {
+    let dir = folder("generic", &["movie.nfo", "sample.mkv", "Heat-trailer.mkv"]);
+    touch(&dir.join("Heat.m4v"), 100);
+    touch(&dir.join("Extras.webm"), 10);
+
+    let videos = find_videos(&dir.join("movie.nfo"), &extensions()).unwrap();
+    assert_eq!(names(videos), vec!["Heat.m4v"]);
+
+    std::fs::remove_file(dir.join("Heat.m4v")).unwrap();
+    std::fs::remove_file(dir.join("Extras.webm")).unwrap();
+    touch(&dir.join("Heat part2.ts"), 1);
+    touch(&dir.join("Heat part1.ts"), 1);
+    let videos = find_videos(&dir.join("movie.nfo"), &extensions()).unwrap();
+    assert_eq!(names(videos), vec!["Heat part1.ts", "Heat part2.ts"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn videos_are_stored_at_scan_time() {
+    let (dir, root, mut db) = library("scan", &["Heat/Heat.nfo", "Heat/Heat.webm"]);
+    let heat = id(&mut db, "heat");
+    assert_eq!(db.get_movie(&heat).unwrap().videos, vec![root.join("Heat/Heat.webm")]);
+
+    // a second part shows up once the first one is gone
+    std::fs::rename(root.join("Heat/Heat.webm"), root.join("Heat/Heat-cd1.webm")).unwrap();
+    touch(&root.join("Heat/Heat-cd2.webm"), 1);
+    db.reload().unwrap();
+    assert_eq!(
+        db.get_movie(&heat).unwrap().videos,
+        vec![root.join("Heat/Heat-cd1.webm"), root.join("Heat/Heat-cd2.webm")]
+    );
+
+    db.set_video_extensions(vec!["mkv".to_string()]).unwrap();
+    assert!(db.get_movie(&heat).unwrap().videos.is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn part_added_later() {
+    let (dir, root, mut db) = library("part-added", &["Heat/Heat.nfo", "Heat/Heat-cd1.mkv"]);
+    let heat = id(&mut db, "heat");
+    assert_eq!(db.get_movie(&heat).unwrap().videos, vec![root.join("Heat/Heat-cd1.mkv")]);
+
+    // the first part is still there, the folder changed
+    touch(&root.join("Heat/Heat-cd2.mkv"), 1);
+    db.reload().unwrap();
+    assert_eq!(
+        db.get_movie(&heat).unwrap().videos,
+        vec![root.join("Heat/Heat-cd1.mkv"), root.join("Heat/Heat-cd2.mkv")]
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,77 @@
+use std::{path::PathBuf, sync::mpsc, time::Duration};
+
+use common::{all_titles, library, write_movie};
+use kr::watch::LibraryWatcher;
+
+mod common;
+
+const LIBRARY: &[&str] = &["Avatar/Avatar.nfo", "Heat/Heat.nfo"];
+
+#[test]
+fn apply_changes() {
+    let (dir, root, mut db) = library("apply", LIBRARY);
+
+    assert!(!db.apply_changes(&[dir.join("elsewhere/Alien.nfo")]).unwrap());
+
+    write_movie(&root.join("Alien/Alien.nfo"), "Alien");
+    std::fs::remove_dir_all(root.join("Heat")).unwrap();
+    assert!(db.apply_changes(&[root.join("Alien/Alien.nfo"), root.join("Heat")]).unwrap());
+    assert_eq!(all_titles(&mut db), vec!["Alien", "Avatar"]);
+
+    let report = db.scan_report();
+    assert_eq!((report.added, report.updated, report.removed), (1, 0, 1));
+
+    // nothing changed since
+    assert!(!db.apply_changes(std::slice::from_ref(&root)).unwrap());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn watcher_reports_new_nfo() {
+    let (dir, root, mut db) = library("watcher", LIBRARY);
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = LibraryWatcher::new(db.video_extensions(), move |paths| tx.send(paths).unwrap()).unwrap();
+    watcher.sync(db.roots());
+    assert_eq!(watcher.watched(), std::slice::from_ref(&root));
+
+    write_movie(&root.join("Alien/Alien.nfo"), "Alien");
+    std::fs::write(root.join("Avatar/poster.jpg"), b"").unwrap();
+
+    // events of the library setup may come first
+    let mut paths = vec![];
+    while !paths.iter().any(|p: &PathBuf| p.starts_with(root.join("Alien"))) {
+        paths.extend(rx.recv_timeout(Duration::from_secs(10)).unwrap());
+    }
+    // the nfo may be written before the new folder is watched, the folder is reported either way
+    assert!(paths.iter().all(|p| !p.ends_with("poster.jpg")), "{paths:?}");
+
+    assert!(db.apply_changes(&paths).unwrap());
+    assert_eq!(all_titles(&mut db), vec!["Alien", "Avatar", "Heat"]);
+
+    db.set_root_enabled(&root, false).unwrap();
+    watcher.sync(db.roots());
+    assert!(watcher.watched().is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn background_scan_of_changes() {
+    let (dir, root, mut db) = library("background", LIBRARY);
+    let other = dir.join("other");
+    write_movie(&other.join("Alien/Alien.nfo"), "Alien");
+    db.add_root(other.clone()).unwrap();
+
+    write_movie(&root.join("Ronin/Ronin.nfo"), "Ronin");
+    write_movie(&other.join("Brazil/Brazil.nfo"), "Brazil");
+
+    assert!(db.background_scan().changed(&[dir.join("elsewhere/Ronin.nfo")]).is_empty());
+
+    // only the root of the reported path is scanned
+    let scan = db.background_scan().changed(&[root.join("Ronin/Ronin.nfo")]);
+    let result = std::thread::spawn(move || scan.run(|_| {})).join().unwrap();
+    assert_eq!(result.report().added, 1);
+    db.finish_scan(result).unwrap();
+    assert_eq!(all_titles(&mut db), vec!["Alien", "Avatar", "Heat", "Ronin"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}